@@ -3,6 +3,7 @@ from .agent.agents import RunnerAgent, Agent
 from .agent.types.job import AgentJobResponse, AgentJobStepRequest
 from .agent.types.job import JobRequest, JobSteps, Step
 from .ceylon import version
+from . import defaults  # noqa: F401
 
 print(f"ceylon version: {version()}")
 print(f"visit https://ceylon.ai for more information")
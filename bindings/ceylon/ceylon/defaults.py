@@ -0,0 +1,15 @@
+# Classes implementing the foreign traits directly only had to define the methods they had
+# before, these fill in the ones added since.
+from ceylon.ceylon import MessageHandler, Processor
+
+
+async def _on_stamped_message(self, agent_id: "str", data: "bytes", time: "int", hlc):
+    await self.on_message(agent_id, data, time)
+
+
+async def _on_stop(self):
+    pass
+
+
+MessageHandler.on_stamped_message = _on_stamped_message
+Processor.on_stop = _on_stop
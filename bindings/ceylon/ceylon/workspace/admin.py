@@ -1,6 +1,6 @@
 import asyncio
 
-from ceylon.ceylon import AdminAgent, AdminAgentConfig, Processor, MessageHandler, EventHandler
+from ceylon.ceylon import AdminAgent, AdminAgentConfig, Processor, MessageHandler, EventHandler, HybridTimestamp
 from ceylon.ceylon.ceylon import uniffi_set_event_loop
 
 
@@ -13,10 +13,16 @@ class Admin(AdminAgent, Processor, MessageHandler, EventHandler):
     async def run(self, inputs: "bytes"):
         pass
 
+    async def on_stop(self):
+        pass
+
     #
     async def on_message(self, agent_id: "str", data: "bytes", time: "int"):
         pass
 
+    async def on_stamped_message(self, agent_id: "str", data: "bytes", time: "int", hlc: HybridTimestamp):
+        await self.on_message(agent_id, data, time)
+
     def run_admin(self, inputs: "bytes", workers):
         import asyncio
 
@@ -26,8 +32,8 @@ class Admin(AdminAgent, Processor, MessageHandler, EventHandler):
             event_loop = asyncio.new_event_loop()
             asyncio.set_event_loop(event_loop)
         if event_loop.is_running():
-            task = event_loop.create_task(self.arun_admin(inputs, workers))
-            return task.result()
+            # The loop cannot be blocked on, the caller awaits the task instead.
+            return event_loop.create_task(self.arun_admin(inputs, workers))
 
         return asyncio.run(self.arun_admin(inputs, workers))
 
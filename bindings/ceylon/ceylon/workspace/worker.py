@@ -1,19 +1,46 @@
+import asyncio
+
 from ceylon.ceylon import WorkerAgent, WorkerAgentConfig, Processor, \
-    MessageHandler
+    MessageHandler, HybridTimestamp
+from ceylon.ceylon.ceylon import uniffi_set_event_loop
 
 
 class Worker(WorkerAgent, Processor, MessageHandler):
 
-    def __init__(self, name="admin", workspace_id="admin", admin_peer=None, admin_port=8888, role="worker"):
+    def __init__(self, name="admin", workspace_id="admin", admin_peer=None, admin_port=8888, role="worker",
+                 admin_address=None, metadata=None):
         super().__init__(config=WorkerAgentConfig(name=name,
                                                   role=role,
                                                   admin_peer=admin_peer,
                                                   admin_port=admin_port,
+                                                  admin_address=admin_address,
+                                                  metadata=metadata,
                                                   work_space_id=workspace_id), processor=self, on_message=self)
 
     async def run(self, inputs: "bytes"):
         pass
 
+    async def on_stop(self):
+        pass
+
     async def on_message(self, agent_id: "str", data: "bytes", time: "int"):
         pass
-   
\ No newline at end of file
+
+    async def on_stamped_message(self, agent_id: "str", data: "bytes", time: "int", hlc: HybridTimestamp):
+        await self.on_message(agent_id, data, time)
+
+    def run_worker(self, inputs: "bytes"):
+        try:
+            event_loop = asyncio.get_running_loop()
+        except RuntimeError:  # No running event loop
+            event_loop = asyncio.new_event_loop()
+            asyncio.set_event_loop(event_loop)
+        if event_loop.is_running():
+            # The loop cannot be blocked on, the caller awaits the task instead.
+            return event_loop.create_task(self.arun_worker(inputs))
+
+        return asyncio.run(self.arun_worker(inputs))
+
+    async def arun_worker(self, inputs: "bytes"):
+        uniffi_set_event_loop(asyncio.get_event_loop())
+        await self.start(inputs)
@@ -56,7 +56,7 @@ class Bidder(Worker):
             if self.budget > message.item.starting_price:
                 random_i = random.randint(100, 1000)
                 bid_amount = min(self.budget, message.item.starting_price * random_i / 100)  # Simple bidding strategy
-                await self.broadcast(pickle.dumps(Bid(bidder=self.name, amount=bid_amount)))
+                await self.send_to_role("admin", pickle.dumps(Bid(bidder=self.name, amount=bid_amount)))
         elif type(message) == AuctionResult:
             if message.winner == self.name:
                 self.budget -= message.winning_bid
@@ -1,3 +1,6 @@
+// The generated scaffolding is not clippy clean.
+#![allow(clippy::empty_line_after_doc_comments)]
+
 mod workspace;
 
 fn version() -> String {
@@ -8,6 +11,10 @@ fn cprint(val: String) {
     info!("{}", val);
 }
 
+fn happened_before(a: HybridTimestamp, b: HybridTimestamp) -> bool {
+    a.happened_before(&b)
+}
+
 fn enable_log(level: String) {
     let subscriber = tracing_subscriber::FmtSubscriber::builder()
         .with_level(true)
@@ -18,7 +25,40 @@ fn enable_log(level: String) {
     tracing::subscriber::set_global_default(subscriber).unwrap();
 }
 
+// Keeps the span exporter alive for the lifetime of the process.
+static TRACING: OnceLock<(Runtime, TracingGuard)> = OnceLock::new();
+
+#[derive(Debug, thiserror::Error)]
+pub enum TracingError {
+    #[error("{reason}")]
+    InitFailed { reason: String },
+}
+
+fn enable_tracing(
+    service_name: String,
+    level: String,
+    otlp_endpoint: Option<String>,
+) -> Result<(), TracingError> {
+    let runtime = Runtime::new().map_err(|e| TracingError::InitFailed {
+        reason: e.to_string(),
+    })?;
+    let guard = runtime
+        .block_on(async {
+            init_tracing(TracingConfig {
+                service_name,
+                level,
+                otlp_endpoint,
+            })
+        })
+        .map_err(|reason| TracingError::InitFailed { reason })?;
+    let _ = TRACING.set((runtime, guard));
+    Ok(())
+}
+
+use sangedama::telemetry::{init_tracing, TracingConfig, TracingGuard};
 use std::str::FromStr;
+use std::sync::OnceLock;
+use tokio::runtime::Runtime;
 use tracing::{info, Level};
 pub use workspace::*;
 uniffi::include_scaffolding!("ceylon");
@@ -1,13 +1,24 @@
 mod admin_agent;
 mod agent;
+mod directory;
 mod message;
+mod outbox;
+mod replay;
 mod worker_agent;
+#[allow(clippy::module_inception)]
 mod workspace;
 
-pub use agent::{AgentDetail, EventHandler, MessageHandler, Processor};
+pub use sangedama::peer::message::clock::HybridTimestamp;
+pub use sangedama::peer::message::data::Priority;
+
+pub use agent::{
+    AgentBase, AgentDetail, EventHandler, MessageHandler, MessageValidator, Processor,
+};
 
 pub use workspace::{WorkSpace, WorkSpaceConfig};
 
-pub use admin_agent::{AdminAgent, AdminAgentConfig};
+pub use admin_agent::{AdminAgent, AdminAgentConfig, ModerationError, StartError};
 
 pub use worker_agent::{WorkerAgent, WorkerAgentConfig};
+
+pub use replay::ReplayError;
@@ -1,25 +1,63 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
-use futures::future::join_all;
-use tokio::sync::{mpsc, Mutex, RwLock};
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, watch, Mutex, RwLock};
 use tokio::{select, signal};
 use tokio_util::sync::CancellationToken;
-use tracing::{error, info};
+use tracing::{error, info, warn, Instrument};
 
 use crate::workspace::agent::{AgentDetail, EventHandler};
-use crate::workspace::message::AgentMessage;
-use crate::{MessageHandler, Processor, WorkerAgent};
-use sangedama::peer::message::data::{EventType, NodeMessage};
+use crate::workspace::directory::AgentDirectory;
+use crate::workspace::message::{AgentMessage, AgentMessageValidator, Recipient};
+use crate::workspace::outbox::Outbox;
+use crate::workspace::replay::{recorded_messages, ReplayError};
+use crate::{MessageHandler, MessageValidator, Processor, WorkerAgent};
+use sangedama::peer::message::compression::CompressionConfig;
+use sangedama::peer::message::data::{EventType, NodeMessage, OutgoingMessage, Priority};
+use sangedama::peer::message::history::HistoryConfig;
 use sangedama::peer::node::{
-    create_key, create_key_from_bytes, get_peer_id, AdminPeer, AdminPeerConfig,
+    create_key, create_key_from_bytes, get_peer_id, AccessPolicy, AdminControl, AdminPeer,
+    AdminPeerConfig, Emitter, WorkspaceConfig,
 };
+use sangedama::peer::{Multiaddr, PeerId};
+
+#[derive(Debug, thiserror::Error)]
+pub enum StartError {
+    #[error("{reason}")]
+    Failed { reason: String },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ModerationError {
+    #[error("{reason}")]
+    InvalidPeer { reason: String },
+    #[error("{reason}")]
+    Failed { reason: String },
+}
 
 #[derive(Clone)]
 pub struct AdminAgentConfig {
     pub name: String,
     pub port: u16,
+    pub history_size: u32,
+    pub history_path: Option<String>,
+    pub ban_list_path: Option<String>,
+    pub listen_addresses: Option<Vec<String>>,
+    pub compress_above: Option<u32>,
+    pub message_ttl_secs: Option<u64>,
+    pub clock_skew_ms: Option<u64>,
+    pub recording_path: Option<String>,
+}
+
+// A workspace hosted next to the agent's own one, with the handlers of its team.
+struct HostedWorkspace {
+    config: WorkspaceConfig,
+    on_message: Arc<dyn MessageHandler>,
+    on_event: Arc<dyn EventHandler>,
 }
 
 pub struct AdminAgent {
@@ -27,10 +65,17 @@ pub struct AdminAgent {
 
     _processor: Arc<Mutex<Arc<dyn Processor>>>,
     _on_message: Arc<Mutex<Arc<dyn MessageHandler>>>,
+    _validator: Arc<Mutex<Option<Arc<dyn MessageValidator>>>>,
     _on_event: Arc<Mutex<Arc<dyn EventHandler>>>,
+    _control: Arc<Mutex<Option<AdminControl>>>,
+    _workspaces: Arc<Mutex<Vec<HostedWorkspace>>>,
+    _emitters: Arc<Mutex<HashMap<String, Emitter>>>,
+    _listen_addresses: std::sync::Mutex<Option<watch::Receiver<Vec<Multiaddr>>>>,
+    _directory: Arc<RwLock<AgentDirectory>>,
+    _launcher: Arc<Mutex<Option<WorkerLauncher>>>,
+    _workers: Arc<Mutex<HashMap<String, Arc<WorkerAgent>>>>,
 
-    pub broadcast_emitter: tokio::sync::mpsc::Sender<Vec<u8>>,
-    pub broadcast_receiver: Arc<Mutex<tokio::sync::mpsc::Receiver<Vec<u8>>>>,
+    _outbox: Outbox,
 
     _peer_id: String,
 
@@ -47,8 +92,6 @@ impl AdminAgent {
         processor: Arc<dyn Processor>,
         on_event: Arc<dyn EventHandler>,
     ) -> Self {
-        let (broadcast_emitter, broadcast_receiver) = tokio::sync::mpsc::channel::<Vec<u8>>(100);
-
         let admin_peer_key = create_key();
         let id = get_peer_id(&admin_peer_key).to_string();
 
@@ -57,11 +100,18 @@ impl AdminAgent {
         Self {
             config,
             _on_message: Arc::new(Mutex::new(on_message)),
+            _validator: Arc::new(Mutex::new(None)),
             _processor: Arc::new(Mutex::new(processor)),
             _on_event: Arc::new(Mutex::new(on_event)),
-
-            broadcast_emitter,
-            broadcast_receiver: Arc::new(Mutex::new(broadcast_receiver)),
+            _control: Arc::new(Mutex::new(None)),
+            _workspaces: Arc::new(Mutex::new(vec![])),
+            _emitters: Arc::new(Mutex::new(HashMap::new())),
+            _listen_addresses: std::sync::Mutex::new(None),
+            _directory: Arc::new(RwLock::new(AgentDirectory::default())),
+            _launcher: Arc::new(Mutex::new(None)),
+            _workers: Arc::new(Mutex::new(HashMap::new())),
+
+            _outbox: Outbox::new(),
             _peer_id: id,
 
             _key: admin_peer_key.to_protobuf_encoding().unwrap(),
@@ -71,22 +121,263 @@ impl AdminAgent {
         }
     }
 
-    pub async fn broadcast(&self, message: Vec<u8>) {
+    pub async fn broadcast(
+        &self,
+        message: Vec<u8>,
+        priority: Option<Priority>,
+        ttl_secs: Option<u64>,
+    ) {
+        self._outbox.broadcast(message, priority, ttl_secs).await;
+    }
+
+    /// Hosts one more workspace on the agent's port, must be called before `start`.
+    /// Only the given peers may take part when `allowed_peers` is set.
+    pub async fn host_workspace(
+        &self,
+        workspace_id: String,
+        on_message: Arc<dyn MessageHandler>,
+        on_event: Arc<dyn EventHandler>,
+        allowed_peers: Option<Vec<String>>,
+    ) {
+        let mut config = WorkspaceConfig::new(workspace_id);
+        if let Some(allowed_peers) = allowed_peers {
+            let peers: HashSet<PeerId> = allowed_peers
+                .iter()
+                .filter_map(|p| match PeerId::from_str(p) {
+                    Ok(peer_id) => Some(peer_id),
+                    Err(_) => {
+                        error!("Invalid peer id {}", p);
+                        None
+                    }
+                })
+                .collect();
+            config.access = AccessPolicy::AllowList(peers);
+        }
+        self._workspaces.lock().await.push(HostedWorkspace {
+            config,
+            on_message,
+            on_event,
+        });
+    }
+
+    pub async fn broadcast_to(
+        &self,
+        workspace_id: String,
+        message: Vec<u8>,
+        priority: Option<Priority>,
+        ttl_secs: Option<u64>,
+    ) {
+        let Some(emitter) = self._emitters.lock().await.get(&workspace_id).cloned() else {
+            error!(
+                "Workspace {} is not hosted by a running agent",
+                workspace_id
+            );
+            return;
+        };
+        let node_message = AgentMessage::new_node_message(message, None);
+        let message = OutgoingMessage {
+            priority: priority.unwrap_or_default(),
+            ttl: ttl_secs.map(Duration::from_secs),
+            ..node_message.to_bytes().into()
+        };
+        if emitter.send(message).await.is_err() {
+            error!("Failed to send broadcast message to {}", workspace_id);
+        }
+    }
+
+    /// The other agents taking part in the workspace, only those of `role` when given.
+    pub async fn list_agents(&self, role: Option<String>) -> Vec<AgentDetail> {
+        self._directory
+            .read()
+            .await
+            .list(role.as_deref(), &self._peer_id)
+    }
+
+    pub async fn send_to(
+        &self,
+        agent_id: String,
+        message: Vec<u8>,
+        priority: Option<Priority>,
+        ttl_secs: Option<u64>,
+    ) {
+        self._outbox
+            .send_to(
+                &self._directory,
+                &self._peer_id,
+                message,
+                Recipient::Id(agent_id),
+                priority,
+                ttl_secs,
+            )
+            .await;
+    }
+
+    pub async fn send_to_name(
+        &self,
+        name: String,
+        message: Vec<u8>,
+        priority: Option<Priority>,
+        ttl_secs: Option<u64>,
+    ) {
+        self._outbox
+            .send_to(
+                &self._directory,
+                &self._peer_id,
+                message,
+                Recipient::Name(name),
+                priority,
+                ttl_secs,
+            )
+            .await;
+    }
+
+    /// Sends to every agent of the role, `admin` for the admin.
+    pub async fn send_to_role(
+        &self,
+        role: String,
+        message: Vec<u8>,
+        priority: Option<Priority>,
+        ttl_secs: Option<u64>,
+    ) {
+        self._outbox
+            .send_to(
+                &self._directory,
+                &self._peer_id,
+                message,
+                Recipient::Role(role),
+                priority,
+                ttl_secs,
+            )
+            .await;
+    }
+
+    /// Takes effect for the messages received from then on, also while the agent is running.
+    pub async fn set_validator(&self, validator: Arc<dyn MessageValidator>) {
+        *self._validator.lock().await = Some(validator);
+    }
+
+    /// Feeds the messages received in a recording to the handlers of each workspace, in their
+    /// recorded order, as if they came from the network.
+    pub async fn replay(&self, path: String) -> Result<(), ReplayError> {
+        let membership = Membership {
+            topic: self.config.name.clone(),
+            me: self.details(),
+            worker_details: Arc::default(),
+            directory: Arc::default(),
+            emitter: None,
+        };
+        let listener = recorded_messages(&path, &self.config.name)?;
+        listen(
+            listener,
+            self._on_message.clone(),
+            self._on_event.clone(),
+            membership.clone(),
+            CancellationToken::new(),
+        )
+        .await;
+        for workspace in self._workspaces.lock().await.iter() {
+            let topic = workspace.config.workspace_id.clone();
+            let listener = recorded_messages(&path, &topic)?;
+            listen(
+                listener,
+                Arc::new(Mutex::new(workspace.on_message.clone())),
+                Arc::new(Mutex::new(workspace.on_event.clone())),
+                Membership {
+                    topic,
+                    ..membership.clone()
+                },
+                CancellationToken::new(),
+            )
+            .await;
+        }
+        Ok(())
+    }
+
+    /// Runs one more worker next to a running agent, with the inputs the agent was started with.
+    /// Fails for a worker that runs already or whose id was added and not removed since.
+    pub async fn add_worker(&self, worker: Arc<WorkerAgent>) -> Result<(), StartError> {
+        let Some(launcher) = self._launcher.lock().await.clone() else {
+            return Err(StartError::Failed {
+                reason: format!("Agent {} is not running", self.config.name),
+            });
+        };
+        self.launch_worker(&launcher, worker).await
+    }
+
+    /// Stops a worker added to the agent, workers running elsewhere are asked to stop.
+    pub async fn remove_worker(&self, agent_id: String) {
+        match self._workers.lock().await.remove(&agent_id) {
+            Some(worker) => worker.stop().await,
+            None => self.stop_worker(agent_id).await,
+        }
+    }
+
+    /// Asks a worker of the workspace to stop, the other agents keep running.
+    pub async fn stop_worker(&self, agent_id: String) {
         let id = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .unwrap()
             .as_nanos() as u64;
-        let node_message = AgentMessage::NodeMessage { message, id };
-        match self.broadcast_emitter.send(node_message.to_bytes()).await {
-            Ok(_) => {}
-            Err(_) => {
-                error!("Failed to send broadcast message");
-            }
+        let message = AgentMessage::StopAgent { id, agent_id };
+        if self
+            ._outbox
+            .send(OutgoingMessage::control(message.to_bytes()))
+            .await
+            .is_err()
+        {
+            error!("Failed to send stop to worker");
         }
     }
 
-    pub async fn start(&self, inputs: Vec<u8>, agents: Vec<Arc<WorkerAgent>>) {
-        self.run_(inputs, agents).await;
+    pub async fn kick(&self, peer_id: String) -> Result<(), ModerationError> {
+        let (control, peer_id) = self.control_for(&peer_id).await?;
+        control
+            .kick(peer_id)
+            .await
+            .map_err(|reason| ModerationError::Failed { reason })
+    }
+
+    pub async fn ban(
+        &self,
+        peer_id: String,
+        duration_secs: Option<u64>,
+    ) -> Result<(), ModerationError> {
+        let (control, peer_id) = self.control_for(&peer_id).await?;
+        control
+            .ban(peer_id, duration_secs.map(Duration::from_secs))
+            .await
+            .map_err(|reason| ModerationError::Failed { reason })
+    }
+
+    pub async fn unban(&self, peer_id: String) -> Result<(), ModerationError> {
+        let (control, peer_id) = self.control_for(&peer_id).await?;
+        control
+            .unban(peer_id)
+            .await
+            .map_err(|reason| ModerationError::Failed { reason })
+    }
+
+    async fn control_for(&self, peer_id: &str) -> Result<(AdminControl, PeerId), ModerationError> {
+        let peer_id = PeerId::from_str(peer_id).map_err(|e| ModerationError::InvalidPeer {
+            reason: format!("Invalid peer id {}: {}", peer_id, e),
+        })?;
+        let control =
+            self._control
+                .lock()
+                .await
+                .clone()
+                .ok_or_else(|| ModerationError::Failed {
+                    reason: format!("Agent {} is not running", self.config.name),
+                })?;
+        Ok((control, peer_id))
+    }
+
+    pub async fn start(
+        &self,
+        inputs: Vec<u8>,
+        agents: Vec<Arc<WorkerAgent>>,
+    ) -> Result<(), StartError> {
+        self.run_(inputs, agents).await
     }
 
     pub async fn stop(&self) {
@@ -100,10 +391,29 @@ impl AdminAgent {
             name: self.config.name.clone(),
             id: self._peer_id.clone(),
             role: "admin".to_string(),
+            addresses: self
+                ._listen_addresses
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|a| a.borrow().iter().map(|a| a.to_string()).collect())
+                .unwrap_or_default(),
+            metadata: HashMap::new(),
         }
     }
-    async fn run_(&self, inputs: Vec<u8>, agents: Vec<Arc<WorkerAgent>>) {
+    async fn run_(&self, inputs: Vec<u8>, agents: Vec<Arc<WorkerAgent>>) -> Result<(), StartError> {
         info!("Agent {} running", self.config.name);
+        let listen_addresses = self
+            .config
+            .listen_addresses
+            .iter()
+            .flatten()
+            .map(|address| {
+                Multiaddr::from_str(address).map_err(|e| StartError::Failed {
+                    reason: format!("Invalid listen address {}: {}", address, e),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
         let runtime = tokio::runtime::Builder::new_multi_thread()
             .enable_all()
@@ -114,15 +424,43 @@ impl AdminAgent {
 
         let handle = runtime.handle().clone();
 
-        let worker_details: RwLock<HashMap<String, AgentDetail>> = RwLock::new(HashMap::new());
+        let worker_details: Arc<RwLock<HashMap<String, AgentDetail>>> =
+            Arc::new(RwLock::new(HashMap::new()));
+        *self._directory.write().await = AgentDirectory::default();
 
         let config = self.config.clone();
-        let admin_config = AdminPeerConfig::new(config.port, config.name.clone());
+        let mut admin_config = AdminPeerConfig::new(config.port, config.name.clone());
+        admin_config.history = HistoryConfig {
+            capacity: config.history_size as usize,
+            path: config.history_path.map(PathBuf::from),
+        };
+        admin_config.ban_list_path = config.ban_list_path.map(PathBuf::from);
+        admin_config.message_ttl = config.message_ttl_secs.map(Duration::from_secs);
+        if let Some(clock_skew_ms) = config.clock_skew_ms {
+            admin_config.clock_skew = Duration::from_millis(clock_skew_ms);
+        }
+        admin_config.recording = config.recording_path.clone().map(PathBuf::from);
+        admin_config.compression = config.compress_above.map(|threshold| CompressionConfig {
+            threshold: threshold as usize,
+            ..CompressionConfig::default()
+        });
+        admin_config.listen_addresses = listen_addresses;
 
         let peer_key = create_key_from_bytes(self._key.clone());
 
-        let (mut peer_, mut peer_listener_) =
-            AdminPeer::create(admin_config.clone(), peer_key).await;
+        let (mut peer_, peer_listener_) =
+            match AdminPeer::create(admin_config.clone(), peer_key).await {
+                Ok(peer) => peer,
+                Err(e) => {
+                    error!("Failed to create admin {}: {}", config.name, e);
+                    return Err(StartError::Failed { reason: e });
+                }
+            };
+        peer_.set_validator(Arc::new(AgentMessageValidator::new(
+            self._validator.clone(),
+        )));
+        *self._control.lock().await = Some(peer_.control());
+        *self._listen_addresses.lock().unwrap() = Some(peer_.listen_addresses());
 
         if peer_.id == self._peer_id {
             info!("Admin peer created {}", peer_.id.clone());
@@ -132,133 +470,94 @@ impl AdminAgent {
         let admin_id = peer_.id.clone();
         let admin_emitter = peer_.emitter();
 
-        let cancel_token_clone = cancel_token.clone();
-        let task_admin = handle.spawn(async move {
-            peer_.run(None, cancel_token_clone).await;
-        });
-
-        let mut worker_tasks = vec![];
-
-        let _inputs = inputs.clone();
-        let admin_id_ = admin_id.clone();
+        for workspace in self._workspaces.lock().await.iter() {
+            let workspace_id = workspace.config.workspace_id.clone();
+            let listener = match peer_.host_workspace(workspace.config.clone()) {
+                Ok(listener) => listener,
+                Err(e) => {
+                    error!("Failed to host workspace {}: {}", workspace_id, e);
+                    continue;
+                }
+            };
+            let emitter = peer_.emitter_for(&workspace_id);
+            if let Some(emitter) = emitter.clone() {
+                self._emitters
+                    .lock()
+                    .await
+                    .insert(workspace_id.clone(), emitter);
+            }
+            let membership = Membership {
+                topic: workspace_id.clone(),
+                me: self.details(),
+                worker_details: worker_details.clone(),
+                directory: Arc::default(),
+                emitter,
+            };
+            handle.spawn(listen(
+                listener,
+                Arc::new(Mutex::new(workspace.on_message.clone())),
+                Arc::new(Mutex::new(workspace.on_event.clone())),
+                membership,
+                cancel_token.clone(),
+            ));
+        }
 
         let cancel_token_clone = cancel_token.clone();
+        let task_admin = handle.spawn(async move { peer_.run(None, cancel_token_clone).await });
+
+        let launcher = WorkerLauncher {
+            handle: handle.clone(),
+            cancel_token: cancel_token.clone(),
+            inputs: inputs.clone(),
+            admin_peer: admin_id.clone(),
+            worker_details: worker_details.clone(),
+        };
+        *self._launcher.lock().await = Some(launcher.clone());
         for agent in agents {
-            let _inputs_ = _inputs.clone();
-            let agent_ = agent.clone();
-            let _admin_id_ = admin_id_.clone();
-            let mut config = agent_.config.clone();
-            config.admin_peer = _admin_id_.clone();
-            let tasks = agent_
-                .run_with_config(
-                    _inputs_.clone(),
-                    config,
-                    handle.clone(),
-                    cancel_token_clone.clone(),
-                )
-                .await;
-            let agent_detail = agent_.details();
-
-            worker_details
-                .write()
-                .await
-                .insert(agent_detail.id.clone(), agent_detail);
-
-            for task in tasks {
-                worker_tasks.push(task);
+            let name = agent.config.name.clone();
+            if let Err(e) = self.launch_worker(&launcher, agent).await {
+                error!("Failed to start worker {}: {}", name, e);
             }
         }
 
         error!("Worker tasks created");
 
-        let worker_tasks = join_all(worker_tasks);
-
         let name = self.config.name.clone();
         let on_message = self._on_message.clone();
         let on_event = self._on_event.clone();
 
         let cancel_token_clone = cancel_token.clone();
 
-        let task_admin_listener = handle.spawn(async move {
-            loop {
-                select! {                    
-                    _ = cancel_token_clone.cancelled() => {
-                        break;
-                    }                   
-                    
-                   event = peer_listener_.recv() => {
-                        if let Some(event) = event {
-                            match event {
-                                NodeMessage::Message{ data, created_by, time} => {
-                                    let agent_message = AgentMessage::from_bytes(data);
-
-                                    match agent_message {
-                                        AgentMessage::NodeMessage { message,.. } => {
-                                            on_message.lock().await.on_message(
-                                                created_by,
-                                                message,
-                                                time
-                                            ).await;
-                                        }
-                                        _ => {
-                                            info!("Agent listener {:?}", agent_message);
-                                        }
-                                    }
-                                }
-                                NodeMessage::Event {
-                                    event,
-                                    ..
-                                }=>{
-                                   match event{
-                                        EventType::Subscribe{
-                                            peer_id,
-                                            topic,
-                                        }=>{
-                                            if let Some(agent) = worker_details.read().await.get(&peer_id) {
-                                                let agent = agent.clone();
-                                                 on_event.lock().await.on_agent_connected(topic,agent)
-                                                .await;
-                                            }
-                                        }
-                                        _ => {
-                                            info!("Admin Received Event {:?}", event);
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        });
-
-        let processor = self._processor.clone();
+        let membership = Membership {
+            topic: self.config.name.clone(),
+            me: self.details(),
+            worker_details,
+            directory: self._directory.clone(),
+            emitter: Some(admin_emitter.clone()),
+        };
+        let task_admin_listener = handle.spawn(listen(
+            peer_listener_,
+            on_message,
+            on_event,
+            membership,
+            cancel_token_clone,
+        ));
+
+        let processor = self._processor.lock().await.clone();
         let processor_input_clone = inputs.clone();
         let cancel_token_clone = cancel_token.clone();
         let run_process = handle.spawn(async move {
-            processor.lock().await.run(processor_input_clone).await;
-            loop {
-                if cancel_token_clone.is_cancelled() {
-                    break;
-                }
-            }
+            processor.run(processor_input_clone).await;
+            // The agent keeps running after `run` returns, until it is stopped.
+            cancel_token_clone.cancelled().await;
         });
 
-        let broadcast_receiver = self.broadcast_receiver.clone();
+        let outbox = self._outbox.clone();
         let cancel_token_clone = cancel_token.clone();
         let run_broadcast = handle.spawn(async move {
-            loop {
-                if cancel_token_clone.is_cancelled() {
-                    break;
-                } else if let Some(raw_data) = broadcast_receiver.lock().await.recv().await {
-                    // info!("Agent broadcast {:?}", raw_data);
-                    match admin_emitter.send(raw_data).await {
-                        Ok(_) => {
-                            continue;
-                        }
-                        Err(_) => continue,
-                    };
-                }
+            select! {
+                _ = cancel_token_clone.cancelled() => {}
+                _ = outbox.forward(admin_emitter) => {}
             }
         });
         let shutdown_recv = self.shutdown_recv.clone();
@@ -278,14 +577,15 @@ impl AdminAgent {
         let shutdown_tx = Arc::new(self.shutdown_send.clone());
         let shutdown_tx = shutdown_tx.clone();
         let admin_id_clone = admin_id.clone();
-        handle
+        let failure = handle
             .spawn(async move {
                 select! {
-                   _ = worker_tasks => {
-                        info!("Agent {} task_admin_listener done", name);
-                    }
-                    _ = task_admin => {
+                    result = task_admin => {
                         info!("Agent {} task_admin done", name);
+                        if let Ok(Err(e)) = result {
+                            error!("Agent {} failed: {}", name, e);
+                            return Some(e);
+                        }
                     }
                     _ = task_admin_listener => {
                         info!("Agent {} task_admin_listener done", name);
@@ -305,8 +605,203 @@ impl AdminAgent {
                         // Perform any necessary cleanup here
                     }
                 }
+                None
             })
             .await
             .unwrap();
+        *self._launcher.lock().await = None;
+        self._workers.lock().await.clear();
+        self._processor.lock().await.clone().on_stop().await;
+        match failure {
+            Some(reason) => Err(StartError::Failed { reason }),
+            None => Ok(()),
+        }
+    }
+
+    // Refuses a worker whose id was added already, the id is held while the worker starts.
+    async fn launch_worker(
+        &self,
+        launcher: &WorkerLauncher,
+        worker: Arc<WorkerAgent>,
+    ) -> Result<(), StartError> {
+        let details = worker.details();
+        {
+            let mut workers = self._workers.lock().await;
+            if workers.contains_key(&details.id) {
+                return Err(StartError::Failed {
+                    reason: format!("Worker {} was added already", details.id),
+                });
+            }
+            workers.insert(details.id.clone(), worker.clone());
+        }
+        launcher
+            .worker_details
+            .write()
+            .await
+            .insert(details.id.clone(), details.clone());
+        let mut config = worker.config.clone();
+        config.admin_peer = launcher.admin_peer.clone();
+        // Each worker runs until stopped on its own or together with the admin.
+        if let Err(e) = worker
+            .run_with_config(
+                launcher.inputs.clone(),
+                config,
+                launcher.handle.clone(),
+                launcher.cancel_token.clone(),
+            )
+            .await
+        {
+            launcher.worker_details.write().await.remove(&details.id);
+            self._workers.lock().await.remove(&details.id);
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+// What workers joining a running agent are started with.
+#[derive(Clone)]
+struct WorkerLauncher {
+    handle: Handle,
+    cancel_token: CancellationToken,
+    inputs: Vec<u8>,
+    admin_peer: String,
+    worker_details: Arc<RwLock<HashMap<String, AgentDetail>>>,
+}
+
+// The agents taking part in a workspace, as seen by its admin.
+#[derive(Clone)]
+struct Membership {
+    topic: String,
+    me: AgentDetail,
+    // Everything known about the agents, from the local workers and the introductions.
+    worker_details: Arc<RwLock<HashMap<String, AgentDetail>>>,
+    directory: Arc<RwLock<AgentDirectory>>,
+    // Where the directory is announced, nothing is announced while replaying.
+    emitter: Option<Emitter>,
+}
+
+impl Membership {
+    // The details of the agent when it is known and was not listed yet.
+    async fn join(&self, peer_id: &str) -> Option<AgentDetail> {
+        let agent = self.worker_details.read().await.get(peer_id).cloned()?;
+        if !self.directory.write().await.join(agent.clone()) {
+            return None;
+        }
+        self.announce().await;
+        Some(agent)
+    }
+
+    async fn introduce(&self, agent: AgentDetail) -> Option<AgentDetail> {
+        let peer_id = agent.id.clone();
+        self.worker_details
+            .write()
+            .await
+            .insert(peer_id.clone(), agent);
+        self.join(&peer_id).await
+    }
+
+    async fn leave(&self, peer_id: &str) {
+        if self.directory.write().await.leave(peer_id) {
+            self.announce().await;
+        }
+    }
+
+    async fn announce(&self) {
+        let Some(emitter) = &self.emitter else {
+            return;
+        };
+        let mut agents = self.directory.read().await.agents();
+        agents.push(self.me.clone());
+        let message = AgentMessage::new_directory(agents);
+        if emitter.send(message.to_bytes()).await.is_err() {
+            error!("Failed to announce the agents of the workspace");
+        }
+    }
+}
+
+// Hands the traffic of a workspace to its handlers until the agent stops.
+async fn listen(
+    mut listener: mpsc::Receiver<NodeMessage>,
+    on_message: Arc<Mutex<Arc<dyn MessageHandler>>>,
+    on_event: Arc<Mutex<Arc<dyn EventHandler>>>,
+    membership: Membership,
+    cancel_token: CancellationToken,
+) {
+    loop {
+        select! {
+            _ = cancel_token.cancelled() => {
+                break;
+            }
+            event = listener.recv() => {
+                let Some(event) = event else {
+                    break;
+                };
+                let span = event.handling_span();
+                match event {
+                    NodeMessage::Message { data, created_by, time, hlc, .. } => {
+                        // Replayed recordings are not validated, they may carry anything.
+                        let agent_message = match AgentMessage::from_bytes(data) {
+                            Ok(agent_message) => agent_message,
+                            Err(e) => {
+                                warn!("Skipping a message of {} that is not an agent message: {}", created_by, e);
+                                continue;
+                            }
+                        };
+
+                        match agent_message {
+                            AgentMessage::NodeMessage { message, to, .. }
+                                if to.as_ref().is_none_or(|to| to.matches(&membership.me)) =>
+                            {
+                                on_message
+                                    .lock()
+                                    .await
+                                    .on_stamped_message(created_by, message, time, hlc)
+                                    .instrument(span)
+                                    .await;
+                            }
+                            // Addressed to other agents.
+                            AgentMessage::NodeMessage { .. } => {}
+                            // created_by is the peer that signed or sent the message.
+                            AgentMessage::Introduction { agent, .. } if agent.id == created_by => {
+                                if let Some(agent) = membership.introduce(agent).await {
+                                    on_event
+                                        .lock()
+                                        .await
+                                        .on_agent_connected(membership.topic.clone(), agent)
+                                        .await;
+                                }
+                            }
+                            _ => {
+                                info!("Agent listener {:?}", agent_message);
+                            }
+                        }
+                    }
+                    NodeMessage::Event {
+                        event, created_by, ..
+                    } => match event {
+                        EventType::Subscribe { peer_id, topic } => {
+                            if let Some(agent) = membership.join(&peer_id).await {
+                                on_event.lock().await.on_agent_connected(topic, agent).await;
+                            }
+                        }
+                        // An agent can only announce that it left itself.
+                        EventType::Unsubscribe { peer_id, .. }
+                        | EventType::AgentLeft { peer_id }
+                            if peer_id == created_by =>
+                        {
+                            membership.leave(&created_by).await;
+                        }
+                        EventType::Unsubscribe { peer_id, .. }
+                        | EventType::AgentLeft { peer_id } => {
+                            warn!("Ignoring {} leaving as announced by {}", peer_id, created_by);
+                        }
+                        _ => {
+                            info!("Admin Received Event {:?}", event);
+                        }
+                    },
+                }
+            }
+        }
     }
 }
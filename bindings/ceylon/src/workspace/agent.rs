@@ -1,5 +1,7 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
 
+use sangedama::peer::message::clock::HybridTimestamp;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -7,6 +9,12 @@ pub struct AgentDetail {
     pub name: String,
     pub id: String,
     pub role: String,
+    /// Where the agent can be reached, only known for a running admin.
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    /// Whatever else the agent tells the workspace about itself.
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
 }
 
 #[async_trait::async_trait]
@@ -17,14 +25,31 @@ pub trait AgentBase {
 #[async_trait::async_trait]
 pub trait MessageHandler: Send + Sync + Debug {
     async fn on_message(&self, agent_id: String, data: Vec<u8>, time: u64);
+    /// What the agents call for every message, `time` is in unix milliseconds and `hlc`
+    /// orders messages causally across machines. Handlers that do not need the stamp
+    /// forward to `on_message`.
+    async fn on_stamped_message(
+        &self,
+        agent_id: String,
+        data: Vec<u8>,
+        time: u64,
+        hlc: HybridTimestamp,
+    );
 }
 
 #[async_trait::async_trait]
 pub trait Processor: Send + Sync + Debug {
     async fn run(&self, input: Vec<u8>) -> ();
+    /// Called once the agent is stopping, `run` gets a short grace period to return.
+    async fn on_stop(&self) -> ();
 }
 
 #[async_trait::async_trait]
 pub trait EventHandler: Send + Sync + Debug {
     async fn on_agent_connected(&self, topic: String, agent: AgentDetail) -> ();
 }
+
+#[async_trait::async_trait]
+pub trait MessageValidator: Send + Sync + Debug {
+    async fn validate(&self, agent_id: String, data: Vec<u8>) -> bool;
+}
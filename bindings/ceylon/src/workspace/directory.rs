@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+
+use crate::workspace::message::Recipient;
+use crate::AgentDetail;
+
+/// The agents taking part in a workspace, kept by the admin from join and leave events and
+/// announced by it to the workers.
+#[derive(Debug, Default)]
+pub(crate) struct AgentDirectory {
+    // Announcements can arrive out of order, through the history of the workspace.
+    version: u64,
+    agents: HashMap<String, AgentDetail>,
+}
+
+impl AgentDirectory {
+    /// Whether the agent was not listed yet.
+    pub(crate) fn join(&mut self, agent: AgentDetail) -> bool {
+        self.agents.insert(agent.id.clone(), agent).is_none()
+    }
+
+    /// Whether the agent was listed.
+    pub(crate) fn leave(&mut self, agent_id: &str) -> bool {
+        self.agents.remove(agent_id).is_some()
+    }
+
+    /// Takes over the agents announced by the admin, unless a newer announcement was seen.
+    pub(crate) fn replace(&mut self, version: u64, agents: Vec<AgentDetail>) {
+        if version <= self.version {
+            return;
+        }
+        self.version = version;
+        self.agents = agents.into_iter().map(|a| (a.id.clone(), a)).collect();
+    }
+
+    pub(crate) fn agents(&self) -> Vec<AgentDetail> {
+        self.agents.values().cloned().collect()
+    }
+
+    /// The ids of the agents other than `except` that a message to `to` is for.
+    pub(crate) fn addressed(&self, to: &Recipient, except: &str) -> Vec<String> {
+        self.agents
+            .values()
+            .filter(|a| a.id != except && to.matches(a))
+            .map(|a| a.id.clone())
+            .collect()
+    }
+
+    /// The agents other than `except`, only those of `role` when given.
+    pub(crate) fn list(&self, role: Option<&str>, except: &str) -> Vec<AgentDetail> {
+        let mut agents: Vec<_> = self
+            .agents
+            .values()
+            .filter(|a| a.id != except && role.is_none_or(|role| a.role == role))
+            .cloned()
+            .collect();
+        agents.sort_by(|a, b| a.name.cmp(&b.name));
+        agents
+    }
+}
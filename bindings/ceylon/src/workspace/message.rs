@@ -1,17 +1,142 @@
+use std::sync::Arc;
+use std::time::SystemTime;
+
 use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{AgentDetail, MessageValidator};
+use sangedama::peer::message::data::NodeMessage;
+use sangedama::peer::message::validation::{self, MessageAcceptance};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum AgentMessage {
-    SystemMessage { id: u64, message: Vec<u8> },
-    NodeMessage { id: u64, message: Vec<u8> },
+    SystemMessage {
+        id: u64,
+        message: Vec<u8>,
+    },
+    NodeMessage {
+        id: u64,
+        message: Vec<u8>,
+        /// Only the matching agents hand the message to their handler, all do when unset.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        to: Option<Recipient>,
+    },
+    /// Sent by the admin whenever agents join or leave the workspace, `id` orders the
+    /// announcements.
+    AgentDirectory {
+        id: u64,
+        agents: Vec<AgentDetail>,
+    },
+    /// Sent by a worker once it sees the admin, so that it is known by its details wherever
+    /// it runs.
+    Introduction {
+        id: u64,
+        agent: AgentDetail,
+    },
+    /// Sent by the admin to stop one of its workers.
+    StopAgent {
+        id: u64,
+        agent_id: String,
+    },
+}
+
+/// The agents a message is addressed to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Recipient {
+    Id(String),
+    Name(String),
+    Role(String),
+}
+
+impl Recipient {
+    pub fn matches(&self, agent: &AgentDetail) -> bool {
+        match self {
+            Recipient::Id(id) => *id == agent.id,
+            Recipient::Name(name) => *name == agent.name,
+            Recipient::Role(role) => *role == agent.role,
+        }
+    }
 }
 
 impl AgentMessage {
+    pub fn new_node_message(message: Vec<u8>, to: Option<Recipient>) -> Self {
+        AgentMessage::NodeMessage {
+            id: message_id(),
+            message,
+            to,
+        }
+    }
+
+    pub fn new_introduction(agent: AgentDetail) -> Self {
+        AgentMessage::Introduction {
+            id: message_id(),
+            agent,
+        }
+    }
+
+    pub fn new_directory(agents: Vec<AgentDetail>) -> Self {
+        AgentMessage::AgentDirectory {
+            id: message_id(),
+            agents,
+        }
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         serde_json::to_vec(self).unwrap()
     }
 
-    pub fn from_bytes(bytes: Vec<u8>) -> Self {
-        serde_json::from_slice(&bytes).unwrap()
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(&bytes)
+    }
+}
+
+fn message_id() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64
+}
+
+/// Runs the agent level validator on the payload of workspace messages. The validator is
+/// read from the agent's slot for every message, so one set while running applies at once.
+#[derive(Debug)]
+pub struct AgentMessageValidator {
+    validator: Arc<Mutex<Option<Arc<dyn MessageValidator>>>>,
+}
+
+impl AgentMessageValidator {
+    pub fn new(validator: Arc<Mutex<Option<Arc<dyn MessageValidator>>>>) -> Self {
+        Self { validator }
+    }
+}
+
+#[async_trait::async_trait]
+impl validation::MessageValidator for AgentMessageValidator {
+    async fn validate(&self, message: &NodeMessage) -> MessageAcceptance {
+        let NodeMessage::Message {
+            created_by, data, ..
+        } = message
+        else {
+            return MessageAcceptance::Accept;
+        };
+        // Payloads that are not agent messages are rejected whether a validator is set or not.
+        let message = match serde_json::from_slice::<AgentMessage>(data) {
+            Ok(AgentMessage::NodeMessage { message, .. }) => message,
+            Ok(
+                AgentMessage::SystemMessage { .. }
+                | AgentMessage::Introduction { .. }
+                | AgentMessage::AgentDirectory { .. }
+                | AgentMessage::StopAgent { .. },
+            ) => return MessageAcceptance::Accept,
+            Err(_) => return MessageAcceptance::Reject,
+        };
+        let Some(validator) = self.validator.lock().await.clone() else {
+            return MessageAcceptance::Accept;
+        };
+        if validator.validate(created_by.clone(), message).await {
+            MessageAcceptance::Accept
+        } else {
+            MessageAcceptance::Reject
+        }
     }
 }
@@ -0,0 +1,118 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use sangedama::peer::message::data::{OutgoingMessage, Priority};
+use sangedama::peer::node::Emitter;
+use sangedama::peer::PeerId;
+use tokio::sync::{mpsc, Mutex, RwLock};
+use tracing::error;
+
+use crate::workspace::directory::AgentDirectory;
+use crate::workspace::message::{AgentMessage, Recipient};
+
+const OUTBOX_CAPACITY: usize = 100;
+
+/// Messages an agent sends, queued per priority until its peer runs and publishes them.
+#[derive(Clone)]
+pub(crate) struct Outbox {
+    data_tx: mpsc::Sender<OutgoingMessage>,
+    control_tx: mpsc::Sender<OutgoingMessage>,
+    data_rx: Arc<Mutex<mpsc::Receiver<OutgoingMessage>>>,
+    control_rx: Arc<Mutex<mpsc::Receiver<OutgoingMessage>>>,
+}
+
+impl Outbox {
+    pub fn new() -> Self {
+        let (data_tx, data_rx) = mpsc::channel(OUTBOX_CAPACITY);
+        let (control_tx, control_rx) = mpsc::channel(OUTBOX_CAPACITY);
+        Self {
+            data_tx,
+            control_tx,
+            data_rx: Arc::new(Mutex::new(data_rx)),
+            control_rx: Arc::new(Mutex::new(control_rx)),
+        }
+    }
+
+    pub async fn send(
+        &self,
+        message: OutgoingMessage,
+    ) -> Result<(), mpsc::error::SendError<OutgoingMessage>> {
+        match message.priority {
+            Priority::Control => self.control_tx.send(message).await,
+            Priority::Data => self.data_tx.send(message).await,
+        }
+    }
+
+    /// Queues a message for every agent of the workspace.
+    pub async fn broadcast(
+        &self,
+        message: Vec<u8>,
+        priority: Option<Priority>,
+        ttl_secs: Option<u64>,
+    ) {
+        let message = OutgoingMessage {
+            priority: priority.unwrap_or_default(),
+            ttl: ttl_secs.map(Duration::from_secs),
+            ..AgentMessage::new_node_message(message, None)
+                .to_bytes()
+                .into()
+        };
+        if self.send(message).await.is_err() {
+            error!("Failed to send broadcast message");
+        }
+    }
+
+    /// Queues a copy of a message for each agent in `directory` other than `me` that `to`
+    /// matches, the rest of the workspace never sees it.
+    pub async fn send_to(
+        &self,
+        directory: &RwLock<AgentDirectory>,
+        me: &str,
+        message: Vec<u8>,
+        to: Recipient,
+        priority: Option<Priority>,
+        ttl_secs: Option<u64>,
+    ) {
+        let recipients = directory.read().await.addressed(&to, me);
+        if recipients.is_empty() {
+            error!("No agent in the workspace to send to {:?}", to);
+            return;
+        }
+        let data = AgentMessage::new_node_message(message, Some(to)).to_bytes();
+        for agent_id in recipients {
+            let Ok(peer_id) = PeerId::from_str(&agent_id) else {
+                error!("Invalid peer id {}", agent_id);
+                continue;
+            };
+            let message = OutgoingMessage {
+                priority: priority.unwrap_or_default(),
+                ttl: ttl_secs.map(Duration::from_secs),
+                to: Some(peer_id),
+                ..data.clone().into()
+            };
+            if self.send(message).await.is_err() {
+                error!("Failed to send message");
+            }
+        }
+    }
+
+    /// Hands the queued messages to the lanes of the peer until it goes away.
+    /// Each lane is fed on its own, so a full data lane never holds back control messages.
+    pub async fn forward(&self, emitter: Emitter) {
+        tokio::join!(
+            forward_lane(&self.control_rx, &emitter),
+            forward_lane(&self.data_rx, &emitter)
+        );
+    }
+}
+
+async fn forward_lane(queue: &Mutex<mpsc::Receiver<OutgoingMessage>>, emitter: &Emitter) {
+    let mut queue = queue.lock().await;
+    while let Some(message) = queue.recv().await {
+        if emitter.send(message).await.is_err() {
+            error!("Failed to send broadcast message");
+            break;
+        }
+    }
+}
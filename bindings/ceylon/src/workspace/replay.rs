@@ -0,0 +1,34 @@
+use std::path::Path;
+
+use tokio::sync::mpsc;
+
+use sangedama::peer::message::data::NodeMessage;
+use sangedama::peer::message::recording::{read_recording, Direction};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("{reason}")]
+    ReadFailed { reason: String },
+}
+
+/// The messages an agent of `topic` received while recording, queued in their original order
+/// on a channel that closes once they are all read.
+pub(crate) fn recorded_messages(
+    path: &str,
+    topic: &str,
+) -> Result<mpsc::Receiver<NodeMessage>, ReplayError> {
+    let entries = read_recording(Path::new(path)).map_err(|e| ReplayError::ReadFailed {
+        reason: format!("Failed to read recording {}: {}", path, e),
+    })?;
+    let messages: Vec<_> = entries
+        .into_iter()
+        .filter(|e| e.direction == Direction::Received && e.topic == topic)
+        .map(|e| e.message)
+        .collect();
+    let (tx, rx) = mpsc::channel(messages.len().max(1));
+    for message in messages {
+        // Cannot fail, the channel has room for all of them.
+        let _ = tx.try_send(message);
+    }
+    Ok(rx)
+}
@@ -1,20 +1,32 @@
+use futures::future::join_all;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::Duration;
 use tokio::runtime::Handle;
-use tokio::select;
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc, Mutex, RwLock};
 use tokio::task::JoinHandle;
+use tokio::{select, signal};
 use tokio_util::sync::CancellationToken;
-use tracing::{error, info};
+use tracing::{error, info, warn, Instrument};
 
+use crate::workspace::admin_agent::StartError;
 use crate::workspace::agent::AgentDetail;
-use crate::workspace::message::AgentMessage;
-use crate::{MessageHandler, Processor};
-use sangedama::peer::message::data::NodeMessage;
+use crate::workspace::directory::AgentDirectory;
+use crate::workspace::message::{AgentMessage, AgentMessageValidator, Recipient};
+use crate::workspace::outbox::Outbox;
+use crate::workspace::replay::{recorded_messages, ReplayError};
+use crate::{MessageHandler, MessageValidator, Processor};
+use sangedama::peer::message::compression::CompressionConfig;
+use sangedama::peer::message::data::{EventType, NodeMessage, OutgoingMessage, Priority};
+use sangedama::peer::message::history::HistoryCursor;
 use sangedama::peer::node::{
-    create_key, create_key_from_bytes, get_peer_id, MemberPeer, MemberPeerConfig,
+    create_key, create_key_from_bytes, get_peer_id, AdminEndpoint, Emitter, MemberPeer,
+    MemberPeerConfig,
 };
+use sangedama::peer::{Multiaddr, PeerId};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WorkerAgentConfig {
@@ -23,6 +35,21 @@ pub struct WorkerAgentConfig {
     pub work_space_id: String,
     pub admin_peer: String,
     pub admin_port: u16,
+    /// Multiaddr of an admin on another machine, the admin is looked up on localhost at
+    /// `admin_port` when unset.
+    #[serde(default)]
+    pub admin_address: Option<String>,
+    pub replay_history: bool,
+    #[serde(default)]
+    pub compress_above: Option<u32>,
+    #[serde(default)]
+    pub message_ttl_secs: Option<u64>,
+    pub clock_skew_ms: Option<u64>,
+    #[serde(default)]
+    pub recording_path: Option<String>,
+    /// Announced to the workspace along with the name and role.
+    #[serde(default)]
+    pub metadata: Option<HashMap<String, String>>,
 }
 
 pub struct WorkerAgent {
@@ -30,60 +57,190 @@ pub struct WorkerAgent {
 
     _processor: Arc<Mutex<Arc<dyn Processor>>>,
     _on_message: Arc<Mutex<Arc<dyn MessageHandler>>>,
+    _validator: Arc<Mutex<Option<Arc<dyn MessageValidator>>>>,
+    _directory: Arc<RwLock<AgentDirectory>>,
 
-    pub broadcast_emitter: tokio::sync::mpsc::Sender<Vec<u8>>,
-    pub broadcast_receiver: Arc<Mutex<tokio::sync::mpsc::Receiver<Vec<u8>>>>,
+    _outbox: Outbox,
 
     _peer_id: String,
     _key: Vec<u8>,
+
+    // Cancels only this worker, a child of the token it was run with.
+    _cancel_token: std::sync::Mutex<Option<CancellationToken>>,
 }
 
+// How long a stopped processor may take to return from `run` before it is abandoned.
+const PROCESSOR_STOP_GRACE: Duration = Duration::from_secs(5);
+
 impl WorkerAgent {
     pub fn new(
         config: WorkerAgentConfig,
         on_message: Arc<dyn MessageHandler>,
         processor: Arc<dyn Processor>,
     ) -> Self {
-        let (broadcast_emitter, broadcast_receiver) = tokio::sync::mpsc::channel::<Vec<u8>>(100);
         let admin_peer_key = create_key();
         let id = get_peer_id(&admin_peer_key).to_string();
         Self {
             config,
             _processor: Arc::new(Mutex::new(processor)),
             _on_message: Arc::new(Mutex::new(on_message)),
+            _validator: Arc::new(Mutex::new(None)),
+            _directory: Arc::new(RwLock::new(AgentDirectory::default())),
 
-            broadcast_emitter,
-            broadcast_receiver: Arc::new(Mutex::new(broadcast_receiver)),
+            _outbox: Outbox::new(),
 
             _peer_id: id,
             _key: admin_peer_key.to_protobuf_encoding().unwrap(),
+
+            _cancel_token: std::sync::Mutex::new(None),
         }
     }
-    pub async fn broadcast(&self, message: Vec<u8>) {
-        let id = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_nanos();
-        let node_message = AgentMessage::NodeMessage {
-            message,
-            id: id as u64,
-        };
-        let message = node_message.to_bytes();
+    pub async fn broadcast(
+        &self,
+        message: Vec<u8>,
+        priority: Option<Priority>,
+        ttl_secs: Option<u64>,
+    ) {
+        self._outbox.broadcast(message, priority, ttl_secs).await;
+    }
+    /// The other agents taking part in the workspace as last announced by the admin, only those
+    /// of `role` when given.
+    pub async fn list_agents(&self, role: Option<String>) -> Vec<AgentDetail> {
+        self._directory
+            .read()
+            .await
+            .list(role.as_deref(), &self._peer_id)
+    }
 
-        match self.broadcast_emitter.send(message).await {
-            Ok(_) => {}
-            Err(_) => {
-                error!("Failed to send broadcast message");
-            }
-        }
+    pub async fn send_to(
+        &self,
+        agent_id: String,
+        message: Vec<u8>,
+        priority: Option<Priority>,
+        ttl_secs: Option<u64>,
+    ) {
+        self._outbox
+            .send_to(
+                &self._directory,
+                &self._peer_id,
+                message,
+                Recipient::Id(agent_id),
+                priority,
+                ttl_secs,
+            )
+            .await;
+    }
+
+    pub async fn send_to_name(
+        &self,
+        name: String,
+        message: Vec<u8>,
+        priority: Option<Priority>,
+        ttl_secs: Option<u64>,
+    ) {
+        self._outbox
+            .send_to(
+                &self._directory,
+                &self._peer_id,
+                message,
+                Recipient::Name(name),
+                priority,
+                ttl_secs,
+            )
+            .await;
+    }
+
+    /// Sends to every agent of the role, `admin` for the admin.
+    pub async fn send_to_role(
+        &self,
+        role: String,
+        message: Vec<u8>,
+        priority: Option<Priority>,
+        ttl_secs: Option<u64>,
+    ) {
+        self._outbox
+            .send_to(
+                &self._directory,
+                &self._peer_id,
+                message,
+                Recipient::Role(role),
+                priority,
+                ttl_secs,
+            )
+            .await;
+    }
+
+    /// Takes effect for the messages received from then on, also while the agent is running.
+    pub async fn set_validator(&self, validator: Arc<dyn MessageValidator>) {
+        *self._validator.lock().await = Some(validator);
+    }
+
+    /// Feeds the messages received in a recording to the message handler, in their recorded
+    /// order, as if they came from the network.
+    pub async fn replay(&self, path: String) -> Result<(), ReplayError> {
+        let listener = recorded_messages(&path, &self.config.work_space_id)?;
+        listen(
+            listener,
+            self._on_message.clone(),
+            self.details(),
+            self.config.admin_peer.clone(),
+            Arc::default(),
+            None,
+            CancellationToken::new(),
+        )
+        .await;
+        Ok(())
     }
-    pub async fn start(&self, _: Vec<u8>) {
-        info!("Not yet implemented");
-        // self.run_with_config(inputs, self.config.clone()).await;
+
+    /// Joins the admin of `config` on its own and runs until `stop` is called.
+    /// Fails right away when the admin is not a valid peer id and address.
+    pub async fn start(&self, inputs: Vec<u8>) -> Result<(), StartError> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let handle = runtime.handle().clone();
+
+        let tasks = self
+            .run_with_config(
+                inputs,
+                self.config.clone(),
+                handle.clone(),
+                CancellationToken::new(),
+            )
+            .await?;
+        let Some(cancel_token) = self._cancel_token.lock().unwrap().clone() else {
+            return Ok(());
+        };
+
+        let name = self.config.name.clone();
+        handle
+            .spawn(async move {
+                select! {
+                    _ = cancel_token.cancelled() => {
+                        info!("Agent {} received shutdown signal, shutting down ...", name);
+                    }
+                    _ = signal::ctrl_c() => {
+                        info!("Agent {} received exit signal", name);
+                        cancel_token.cancel();
+                    }
+                }
+                join_all(tasks).await;
+                info!("Agent {} stopped", name);
+            })
+            .await
+            .unwrap();
+        Ok(())
     }
 
+    /// Stops this worker alone, its peer leaves the workspace and its processor is told to stop.
     pub async fn stop(&self) {
         info!("Agent {} stop called", self.config.name);
+
+        match self._cancel_token.lock().unwrap().as_ref() {
+            Some(cancel_token) => cancel_token.cancel(),
+            None => error!("Agent {} is not running", self.config.name),
+        }
     }
 
     pub fn details(&self) -> AgentDetail {
@@ -91,6 +248,8 @@ impl WorkerAgent {
             name: self.config.name.clone(),
             id: self._peer_id.clone(),
             role: self.config.role.clone(),
+            addresses: vec![],
+            metadata: self.config.metadata.clone().unwrap_or_default(),
         }
     }
 }
@@ -102,19 +261,49 @@ impl WorkerAgent {
         worker_agent_config: WorkerAgentConfig,
         runtime: Handle,
         cancellation_token: CancellationToken,
-    ) -> Vec<JoinHandle<()>> {
+    ) -> Result<Vec<JoinHandle<()>>, StartError> {
         info!("Agent {} running", self.config.name);
 
         let config = worker_agent_config.clone();
-        let member_config = MemberPeerConfig::new(
+        let admin_peer = PeerId::from_str(&config.admin_peer).map_err(|e| StartError::Failed {
+            reason: format!("Invalid admin peer id {}: {}", config.admin_peer, e),
+        })?;
+        let mut member_config = MemberPeerConfig::new(
             config.name.clone(),
             config.work_space_id.clone(),
             config.admin_peer.clone(),
             config.admin_port,
         );
+        if let Some(address) = &config.admin_address {
+            let address = Multiaddr::from_str(address).map_err(|e| StartError::Failed {
+                reason: format!("Invalid admin address {}: {}", address, e),
+            })?;
+            member_config.admin = Some(AdminEndpoint::new(admin_peer, address));
+        }
+        if config.replay_history {
+            member_config.history_replay = Some(HistoryCursor::Beginning);
+        }
+        member_config.message_ttl = config.message_ttl_secs.map(Duration::from_secs);
+        if let Some(clock_skew_ms) = config.clock_skew_ms {
+            member_config.clock_skew = Duration::from_millis(clock_skew_ms);
+        }
+        member_config.recording = config.recording_path.clone().map(PathBuf::from);
+        member_config.compression = config.compress_above.map(|threshold| CompressionConfig {
+            threshold: threshold as usize,
+            ..CompressionConfig::default()
+        });
         let peer_key = create_key_from_bytes(self._key.clone());
-        let (mut peer_, mut peer_listener_) =
-            MemberPeer::create(member_config.clone(), peer_key).await;
+        let (mut peer_, peer_listener_) =
+            match MemberPeer::create(member_config.clone(), peer_key).await {
+                Ok(peer) => peer,
+                Err(e) => {
+                    error!("Failed to create worker {}: {}", config.name, e);
+                    return Err(StartError::Failed { reason: e });
+                }
+            };
+        peer_.set_validator(Arc::new(AgentMessageValidator::new(
+            self._validator.clone(),
+        )));
         if peer_.id == self._peer_id {
             info!("Worker peer created {}", peer_.id.clone());
         } else {
@@ -122,7 +311,19 @@ impl WorkerAgent {
         }
         let peer_emitter = peer_.emitter();
 
-        let is_request_to_shutdown = false;
+        let cancellation_token = cancellation_token.child_token();
+        {
+            // The peer created above is dropped unused when the worker runs already.
+            let mut running = self._cancel_token.lock().unwrap();
+            if running.as_ref().is_some_and(|token| !token.is_cancelled()) {
+                return Err(StartError::Failed {
+                    reason: format!("Agent {} is running already", self.config.name),
+                });
+            }
+            *running = Some(cancellation_token.clone());
+        }
+        *self._directory.write().await = AgentDirectory::default();
+
         let cancellation_token_clone = cancellation_token.clone();
         let task_admin = runtime.spawn(async move {
             peer_.run(cancellation_token_clone).await;
@@ -130,65 +331,153 @@ impl WorkerAgent {
 
         let on_message = self._on_message.clone();
         let cancellation_token_clone = cancellation_token.clone();
-        let task_admin_listener = runtime.spawn(async move {
-            loop {
-                if is_request_to_shutdown {
-                    break;
-                }
-                select! {
-                    _ = cancellation_token_clone.cancelled() => {
-                        break;
-                    }
-                   event = peer_listener_.recv() => {
-                        if let Some(event) = event {
-                            match event {
-                                NodeMessage::Message{ data, created_by, time} => {
-                                   let agent_message = AgentMessage::from_bytes(data);
-
-                                    match agent_message {
-                                        AgentMessage::NodeMessage { message,.. } => {
-                                            on_message.lock().await.on_message(
-                                                created_by,
-                                                message,
-                                                time
-                                            ).await;
-                                        }
-                                        _ => {
-                                            info!("Agent listener {:?}", agent_message);
-                                        }
-                                    }
-                                }
-                                _ => {
-                                    info!("Agent listener {:?}", event);
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        });
+        let task_admin_listener = runtime.spawn(listen(
+            peer_listener_,
+            on_message,
+            self.details(),
+            config.admin_peer.clone(),
+            self._directory.clone(),
+            Some(peer_emitter.clone()),
+            cancellation_token_clone,
+        ));
 
-        let processor = self._processor.clone();
+        let processor = self._processor.lock().await.clone();
+        let name = config.name.clone();
+        let cancellation_token_clone = cancellation_token.clone();
         let run_process = runtime.spawn(async move {
-            processor.lock().await.run(inputs).await;
+            let run = processor.run(inputs);
+            tokio::pin!(run);
+            let finished = select! {
+                _ = &mut run => true,
+                _ = cancellation_token_clone.cancelled() => false,
+            };
+            if finished {
+                cancellation_token_clone.cancelled().await;
+            }
+            processor.on_stop().await;
+            if !finished
+                && tokio::time::timeout(PROCESSOR_STOP_GRACE, run)
+                    .await
+                    .is_err()
+            {
+                error!("Processor of agent {} did not stop in time", name);
+            }
         });
 
-        let broadcast_receiver = self.broadcast_receiver.clone();
+        let outbox = self._outbox.clone();
         let cancellation_token_clone = cancellation_token.clone();
         let run_broadcast = runtime.spawn(async move {
-            loop {
-                if is_request_to_shutdown {
-                    break;
-                }
+            select! {
+                _ = cancellation_token_clone.cancelled() => {}
+                _ = outbox.forward(peer_emitter) => {}
+            }
+        });
+
+        Ok(vec![
+            task_admin,
+            task_admin_listener,
+            run_process,
+            run_broadcast,
+        ])
+    }
+}
 
-                if cancellation_token_clone.is_cancelled() {
+// Hands the workspace traffic to the message handler until the worker stops, which the admin
+// can ask for. The worker introduces itself through `emitter` once every time it connects to
+// the admin, nothing is sent while replaying.
+async fn listen(
+    mut listener: mpsc::Receiver<NodeMessage>,
+    on_message: Arc<Mutex<Arc<dyn MessageHandler>>>,
+    me: AgentDetail,
+    admin_peer: String,
+    directory: Arc<RwLock<AgentDirectory>>,
+    emitter: Option<Emitter>,
+    cancel_token: CancellationToken,
+) {
+    let mut introduced = false;
+    loop {
+        select! {
+            _ = cancel_token.cancelled() => {
+                break;
+            }
+            event = listener.recv() => {
+                let Some(event) = event else {
                     break;
-                } else if let Some(raw_data) = broadcast_receiver.lock().await.recv().await {
-                    peer_emitter.send(raw_data).await.unwrap();
+                };
+                let span = event.handling_span();
+                match event {
+                    NodeMessage::Message { data, created_by, time, hlc, .. } => {
+                        // Replayed recordings are not validated, they may carry anything.
+                        let agent_message = match AgentMessage::from_bytes(data) {
+                            Ok(agent_message) => agent_message,
+                            Err(e) => {
+                                warn!("Skipping a message of {} that is not an agent message: {}", created_by, e);
+                                continue;
+                            }
+                        };
+
+                        match agent_message {
+                            AgentMessage::NodeMessage { message, to, .. }
+                                if to.as_ref().is_none_or(|to| to.matches(&me)) =>
+                            {
+                                on_message
+                                    .lock()
+                                    .await
+                                    .on_stamped_message(created_by, message, time, hlc)
+                                    .instrument(span)
+                                    .await;
+                            }
+                            // Messages only get here when created_by is the peer that signed or
+                            // sent them, so only the admin can announce the agents or stop one.
+                            AgentMessage::AgentDirectory { id, agents } if created_by == admin_peer => {
+                                directory.write().await.replace(id, agents);
+                            }
+                            AgentMessage::AgentDirectory { .. } => {
+                                warn!("Agent {} ignored agents announced by {}", me.name, created_by);
+                            }
+                            AgentMessage::StopAgent { agent_id, .. }
+                                if agent_id == me.id && created_by == admin_peer =>
+                            {
+                                info!("Agent {} stopped by admin", me.name);
+                                cancel_token.cancel();
+                            }
+                            // Addressed to other agents.
+                            AgentMessage::NodeMessage { .. } => {}
+                            // Meant for the admin.
+                            AgentMessage::Introduction { .. } => {}
+                            _ => {
+                                info!("Agent listener {:?}", agent_message);
+                            }
+                        }
+                    }
+                    NodeMessage::Event {
+                        event: EventType::Subscribe { peer_id, .. },
+                        ..
+                    } if peer_id == admin_peer && !introduced => {
+                        let Some(emitter) = &emitter else {
+                            continue;
+                        };
+                        introduced = true;
+                        let introduction = AgentMessage::new_introduction(me.clone());
+                        if emitter
+                            .send(OutgoingMessage::control(introduction.to_bytes()))
+                            .await
+                            .is_err()
+                        {
+                            error!("Agent {} failed to introduce itself", me.name);
+                        }
+                    }
+                    NodeMessage::Event {
+                        event: EventType::Unsubscribe { peer_id, .. },
+                        ..
+                    } if peer_id == admin_peer => {
+                        introduced = false;
+                    }
+                    _ => {
+                        info!("Agent listener {:?}", event);
+                    }
                 }
             }
-        });
-
-        vec![task_admin, task_admin_listener, run_process, run_broadcast]
+        }
     }
 }
@@ -1,2 +1,3 @@
 mod common;
 pub mod peer;
+pub mod telemetry;
@@ -1,200 +1,435 @@
-mod peer;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 
-use std::net::Ipv4Addr;
-use std::str::FromStr;
-
-use libp2p::multiaddr::Protocol;
-use libp2p::{Multiaddr, PeerId};
+use clap::{Args, Parser, Subcommand};
+use libp2p::{identity, Multiaddr, PeerId};
+use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::select;
+use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
-use tracing::info;
+use tracing::error;
 
-use peer::message::data::NodeMessage;
-use peer::node::{
-    create_key, get_peer_id, AdminPeer, AdminPeerConfig, MemberPeer, MemberPeerConfig,
+use sangedama::peer::message::clock::unix_millis;
+use sangedama::peer::message::compression::CompressionConfig;
+use sangedama::peer::message::data::{
+    EventType, NodeMessage, OutgoingMessage, Priority, DEFAULT_CLOCK_SKEW,
+};
+use sangedama::peer::message::history::{HistoryConfig, HistoryCursor};
+use sangedama::peer::node::{
+    create_key, load_or_create_key, AdminEndpoint, AdminPeer, AdminPeerConfig, Emitter, MemberPeer,
+    MemberPeerConfig, ShutdownConfig, WorkspaceConfig,
 };
+use sangedama::telemetry::{init_tracing, TracingConfig};
+
+// How long one-shot commands wait for the workspace before giving up.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(10);
+// Gives the swarm a moment to flush a published message before shutting down.
+const FLUSH_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Parser)]
+#[command(
+    name = "sangedama",
+    version,
+    about = "Run and inspect Ceylon workspaces"
+)]
+struct Cli {
+    /// Log filter used when RUST_LOG is not set.
+    #[arg(long, global = true, default_value = "warn")]
+    log_level: String,
+    /// OTLP/HTTP endpoint spans are exported to.
+    #[arg(long, global = true, env = "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT")]
+    otlp_endpoint: Option<String>,
+    /// Wrap sent messages the way ceylon agents send them. Ceylon agents reject raw payloads.
+    #[arg(long, global = true)]
+    ceylon: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Host workspaces, printing their traffic and broadcasting lines read from stdin to the first.
+    Admin(AdminArgs),
+    /// Join a workspace, printing its traffic and broadcasting lines read from stdin.
+    Member(JoinArgs),
+    /// Broadcast a single message to a workspace.
+    Send {
+        #[command(flatten)]
+        join: JoinArgs,
+        message: String,
+        /// Seconds after which receivers drop the message unread.
+        #[arg(long)]
+        ttl: Option<u64>,
+        /// Send on the control lane, ahead of queued data.
+        #[arg(long)]
+        control: bool,
+    },
+    /// Print the traffic of a workspace.
+    Tail {
+        #[command(flatten)]
+        join: JoinArgs,
+        /// Replay the history kept by the admin first.
+        #[arg(long)]
+        replay: bool,
+        /// Print connection, registration and subscription changes too.
+        #[arg(long)]
+        network: bool,
+    },
+    /// List the peers registered in a workspace.
+    Peers(JoinArgs),
+}
+
+#[derive(Args)]
+struct AdminArgs {
+    /// Repeat to host several workspaces on the same port.
+    #[arg(long, required = true)]
+    workspace: Vec<String>,
+    /// Repeat to listen on several addresses, e.g. /ip6/::/tcp/7846/ws.
+    #[arg(long, default_value = "/ip4/0.0.0.0/udp/7845/quic-v1")]
+    listen: Vec<Multiaddr>,
+    /// Key file to keep the peer id across restarts, created if missing.
+    #[arg(long)]
+    key_file: Option<PathBuf>,
+    /// JSON lines file the history of the first workspace is kept in.
+    #[arg(long)]
+    history_path: Option<PathBuf>,
+    /// Compress outgoing payloads of at least this many bytes with zstd.
+    #[arg(long)]
+    compress_above: Option<usize>,
+    /// JSON lines file every message sent and received is recorded to.
+    #[arg(long)]
+    record: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct JoinArgs {
+    #[arg(long)]
+    workspace: String,
+    /// Address of the admin, e.g. /ip4/127.0.0.1/udp/7845/quic-v1.
+    #[arg(long)]
+    admin: Multiaddr,
+    /// Peer id of the admin.
+    #[arg(long)]
+    admin_peer: PeerId,
+    #[arg(long, default_value = "sangedama-cli")]
+    name: String,
+    /// Key file to keep the peer id across restarts, created if missing.
+    #[arg(long)]
+    key_file: Option<PathBuf>,
+    /// Compress outgoing payloads of at least this many bytes with zstd.
+    #[arg(long)]
+    compress_above: Option<usize>,
+    /// JSON lines file every message sent and received is recorded to.
+    #[arg(long)]
+    record: Option<PathBuf>,
+}
 
 #[tokio::main]
 async fn main() {
-    let subscriber = tracing_subscriber::FmtSubscriber::new();
-    // use that subscriber to process traces emitted after this point
-    tracing::subscriber::set_global_default(subscriber).unwrap();
-    let workspace_id = "workspace-test".to_string();
-    info!("Starting {}", workspace_id);
-
-    let admin_port = 7845;
-    let admin_config = AdminPeerConfig::new(admin_port, workspace_id.clone());
-    let admin_key = create_key();
-    let admin_id_from_key = get_peer_id(&admin_key);
-    let (mut admin_peer, mut admin_listener) =
-        AdminPeer::create(admin_config.clone(), admin_key).await;
-    let admin_id = admin_peer.id.clone();
+    let cli = Cli::parse();
 
-    let cancel_token = CancellationToken::new();
+    let mut tracing_config = TracingConfig::new("sangedama".to_string());
+    tracing_config.level = cli.log_level;
+    tracing_config.otlp_endpoint = cli.otlp_endpoint;
+    let _tracing = init_tracing(tracing_config).unwrap_or_else(|e| fail(&e));
 
-    if admin_id.to_string() == admin_id_from_key.to_string() {
-        info!("Admin peer created with id: {}", admin_id);
-    }
-
-    let admin_emitter = admin_peer.emitter();
+    let cancel_token = CancellationToken::new();
     let cancel_token_clone = cancel_token.clone();
-    let task_admin = tokio::task::spawn(async move {
-        admin_peer.run(None, cancel_token_clone).await;
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            cancel_token_clone.cancel();
+        }
     });
 
-    let task_admin_listener = tokio::spawn(async move {
-        loop {
-            select! {
-               event = admin_listener.recv() => {
-                    if event.is_some() {
-                        let event = event.unwrap();
-                        match event{
-                            NodeMessage::Message{ data,created_by, ..} => {
-                                info!("Admin listener Message {:?} from {:?}",String::from_utf8(data),created_by);
-                            }
-                            _ => {
-                                info!("peer1 listener {:?}", event);
-                            }
-                        }
-                    }
+    let ceylon = cli.ceylon;
+    match cli.command {
+        Command::Admin(args) => run_admin(args, ceylon, cancel_token).await,
+        Command::Member(join) => run_member(join, ceylon, cancel_token).await,
+        Command::Send {
+            join,
+            message,
+            ttl,
+            control,
+        } => send(join, payload(message, ceylon), ttl, control, cancel_token).await,
+        Command::Tail {
+            join,
+            replay,
+            network,
+        } => tail(join, replay, network, cancel_token).await,
+        Command::Peers(join) => peers(join, cancel_token).await,
+    }
+}
+
+async fn run_admin(args: AdminArgs, ceylon: bool, cancel_token: CancellationToken) {
+    let mut config = AdminPeerConfig::new(0, args.workspace[0].clone());
+    config.listen_addresses = args.listen;
+    config.compression = compression(args.compress_above);
+    config.recording = args.record;
+    config.history = HistoryConfig {
+        path: args.history_path,
+        ..HistoryConfig::default()
+    };
+    let (mut admin, listener) = AdminPeer::create(config, load_key(&args.key_file))
+        .await
+        .unwrap_or_else(|e| fail(&e));
+    let mut listeners = vec![(args.workspace[0].clone(), listener)];
+    for workspace in &args.workspace[1..] {
+        let listener = admin
+            .host_workspace(WorkspaceConfig::new(workspace.clone()))
+            .unwrap_or_else(|e| fail(&e));
+        listeners.push((workspace.clone(), listener));
+    }
+    println!(
+        "Admin {} hosting workspace {}",
+        admin.id,
+        args.workspace.join(", ")
+    );
+    let mut addresses = admin.listen_addresses();
+    tokio::spawn(async move {
+        let mut printed = vec![];
+        while addresses.changed().await.is_ok() {
+            for address in addresses.borrow_and_update().iter() {
+                if !printed.contains(address) {
+                    println!("Listening on {}", address);
+                    printed.push(address.clone());
                 }
             }
         }
     });
 
-    let task_run_admin = tokio::task::spawn(async move {
-        loop {
-            admin_emitter
-                .send("Admin Send regards".to_string().as_bytes().to_vec())
-                .await
-                .unwrap();
-            tokio::time::sleep(std::time::Duration::from_millis(1000)).await;
-        }
+    forward_stdin(admin.emitter(), ceylon, cancel_token.clone());
+    // Only tell the workspaces apart when there is more than one.
+    let labelled = listeners.len() > 1;
+    let printers: Vec<_> = listeners
+        .into_iter()
+        .map(|(workspace, listener)| {
+            tokio::spawn(print_messages(listener, labelled.then_some(workspace)))
+        })
+        .collect();
+    if let Err(e) = admin.run(None, cancel_token).await {
+        fail(&e);
+    }
+    for printer in printers {
+        printer.abort();
+    }
+}
+
+async fn run_member(join: JoinArgs, ceylon: bool, cancel_token: CancellationToken) {
+    let (mut member, listener) = create_member(&join, None).await;
+    println!("Member {} joining workspace {}", member.id, join.workspace);
+
+    forward_stdin(member.emitter(), ceylon, cancel_token.clone());
+    let printer = tokio::spawn(print_messages(listener, None));
+    member.run(cancel_token).await;
+    printer.abort();
+}
+
+async fn send(
+    join: JoinArgs,
+    message: Vec<u8>,
+    ttl: Option<u64>,
+    control: bool,
+    cancel_token: CancellationToken,
+) {
+    let (mut member, mut listener) = create_member(&join, None).await;
+    let emitter = member.emitter();
+    let task_member = tokio::spawn({
+        let cancel_token = cancel_token.clone();
+        async move { member.run(cancel_token).await }
     });
 
-    // Here we create localhost address to connect peer with admin
-    let peer_dial_address = Multiaddr::empty()
-        .with(Protocol::Ip4(Ipv4Addr::LOCALHOST))
-        .with(Protocol::Udp(admin_port))
-        .with(Protocol::QuicV1);
-
-    let peer_1 = create_client(
-        workspace_id.clone(),
-        admin_id.clone(),
-        admin_port,
-        peer_dial_address.clone(),
-        "peer1".to_string(),
-        cancel_token.clone(),
-    )
-    .await;
-    let peer_2 = create_client(
-        workspace_id.clone(),
-        admin_id.clone(),
-        admin_port,
-        peer_dial_address.clone(),
-        "peer2".to_string(),
-        cancel_token.clone(),
-    )
-    .await;
-    let peer_3 = create_client(
-        workspace_id.clone(),
-        admin_id.clone(),
-        admin_port,
-        peer_dial_address.clone(),
-        "peer3".to_string(),
-        cancel_token.clone(),
-    )
-    .await;
-    let peer_4 = create_client(
-        workspace_id.clone(),
-        admin_id.clone(),
-        admin_port,
-        peer_dial_address.clone(),
-        "peer4".to_string(),
-        cancel_token.clone(),
-    )
+    let admin_peer = join.admin_peer.to_string();
+    wait_for(&mut listener, &cancel_token, |event| {
+        matches!(event, EventType::Subscribe { peer_id, topic } if *peer_id == admin_peer && *topic == join.workspace)
+    })
     .await;
+    let message = OutgoingMessage {
+        ttl: ttl.map(Duration::from_secs),
+        priority: if control {
+            Priority::Control
+        } else {
+            Priority::Data
+        },
+        ..OutgoingMessage::new(message)
+    };
+    if let Err(e) = emitter.send(message).await {
+        error!("Failed to send message: {:?}", e);
+    }
+    tokio::time::sleep(FLUSH_DELAY).await;
 
-    task_admin.await.unwrap();
-    task_admin_listener.await.unwrap();
-    task_run_admin.await.unwrap();
+    cancel_token.cancel();
+    task_member.await.unwrap();
+}
 
-    peer_1.await.unwrap();
-    peer_2.await.unwrap();
-    peer_3.await.unwrap();
-    peer_4.await.unwrap();
+async fn tail(join: JoinArgs, replay: bool, network: bool, cancel_token: CancellationToken) {
+    let replay = replay.then_some(HistoryCursor::Beginning);
+    let (mut member, listener) = create_member(&join, replay).await;
+    if network {
+        let mut events = member.network_events();
+        tokio::spawn(async move {
+            while let Ok(event) = events.recv().await {
+                println!("{} {:?}", format_time(unix_millis()), event);
+            }
+        });
+    }
+
+    let printer = tokio::spawn(print_messages(listener, None));
+    member.run(cancel_token).await;
+    printer.abort();
 }
 
-async fn create_client(
-    workspace_id: String,
-    admin_id: String,
-    admin_port: u16,
-    peer_dial_address: Multiaddr,
-    name: String,
-    cancel_token: CancellationToken,
-) -> tokio::task::JoinHandle<()> {
-    let member_key = create_key();
-    let member_id_from_key = get_peer_id(&member_key);
-    let (mut peer2, mut peer2_listener) = MemberPeer::create(
-        MemberPeerConfig {
-            name: name.clone(),
-            workspace_id: workspace_id.clone(),
-            admin_peer: PeerId::from_str(&admin_id).unwrap(),
-            rendezvous_point_address: peer_dial_address.clone(),
-        },
-        member_key,
-    )
+async fn peers(join: JoinArgs, cancel_token: CancellationToken) {
+    let (mut member, mut listener) = create_member(&join, None).await;
+    let task_member = tokio::spawn({
+        let cancel_token = cancel_token.clone();
+        async move { member.run(cancel_token).await }
+    });
+
+    let event = wait_for(&mut listener, &cancel_token, |event| {
+        matches!(event, EventType::PeersDiscovered { .. })
+    })
     .await;
+    if let EventType::PeersDiscovered { peers } = event {
+        println!("{}", join.admin_peer);
+        for peer in peers {
+            println!("{}", peer);
+        }
+    }
+
+    cancel_token.cancel();
+    task_member.await.unwrap();
+}
+
+async fn create_member(
+    join: &JoinArgs,
+    history_replay: Option<HistoryCursor>,
+) -> (MemberPeer, mpsc::Receiver<NodeMessage>) {
+    let config = MemberPeerConfig {
+        name: join.name.clone(),
+        workspace_id: join.workspace.clone(),
+        admin: Some(AdminEndpoint::new(join.admin_peer, join.admin.clone())),
+        history_replay,
+        standby_admins: vec![],
+        dht: None,
+        peer_score: None,
+        registration_ttl: None,
+        compression: compression(join.compress_above),
+        recording: join.record.clone(),
+        message_ttl: None,
+        clock_skew: DEFAULT_CLOCK_SKEW,
+        shutdown: ShutdownConfig::default(),
+    };
+    MemberPeer::create(config, load_key(&join.key_file))
+        .await
+        .unwrap_or_else(|e| fail(&e))
+}
 
-    let peer2_emitter = peer2.emitter();
-    let peer2_id = peer2.id.clone();
+fn compression(threshold: Option<usize>) -> Option<CompressionConfig> {
+    threshold.map(|threshold| CompressionConfig {
+        threshold,
+        ..CompressionConfig::default()
+    })
+}
 
-    if member_id_from_key.to_string() == peer2_id.to_string() {
-        info!("{} {} created", name.clone(), peer2_id);
+fn load_key(key_file: &Option<PathBuf>) -> identity::Keypair {
+    match key_file {
+        Some(path) => load_or_create_key(path)
+            .unwrap_or_else(|e| fail(&format!("Failed to load key {:?}: {}", path, e))),
+        None => create_key(),
     }
-    let cancel_token_clone = cancel_token.clone();
-    let task_peer_2 = tokio::task::spawn(async move {
-        peer2.run(cancel_token_clone).await;
-    });
+}
+
+// Waits for the first event matching `predicate`, exiting when it does not come in time.
+async fn wait_for(
+    listener: &mut mpsc::Receiver<NodeMessage>,
+    cancel_token: &CancellationToken,
+    predicate: impl Fn(&EventType) -> bool,
+) -> EventType {
+    let timeout = tokio::time::sleep(JOIN_TIMEOUT);
+    tokio::pin!(timeout);
+    loop {
+        select! {
+            _ = cancel_token.cancelled() => std::process::exit(130),
+            _ = &mut timeout => fail("Timed out waiting for the workspace"),
+            message = listener.recv() => match message {
+                Some(NodeMessage::Event { event, .. }) if predicate(&event) => return event,
+                Some(_) => {}
+                None => fail("Peer stopped"),
+            },
+        }
+    }
+}
 
-    let name_clone = name.clone();
-    let task_peer_2_listener = tokio::spawn(async move {
+// The bytes sent for `text`, in the envelope ceylon agents expect when `ceylon` is set.
+fn payload(text: String, ceylon: bool) -> Vec<u8> {
+    if !ceylon {
+        return text.into_bytes();
+    }
+    let id = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64;
+    serde_json::to_vec(&serde_json::json!({
+        "NodeMessage": { "id": id, "message": text.into_bytes() }
+    }))
+    .unwrap()
+}
+
+fn forward_stdin(emitter: Emitter, ceylon: bool, cancel_token: CancellationToken) {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
         loop {
             select! {
-                event = peer2_listener.recv() => {
-                  if event.is_some() {
-                        let event = event.unwrap();
-                        match event{
-                            NodeMessage::Message{ data,created_by, ..} => {
-                                info!("{} {} listener Message {:?} from {:?}",name.clone(),peer2_id, String::from_utf8(data),created_by);
-                            }
-                            _ => {
-                                info!("{} listener {:?}",name.clone(), event);
-                            }
+                _ = cancel_token.cancelled() => break,
+                line = lines.next_line() => match line {
+                    Ok(Some(line)) => {
+                        if emitter.send(payload(line, ceylon)).await.is_err() {
+                            break;
                         }
                     }
-                }
+                    Ok(None) => break,
+                    Err(e) => {
+                        error!("Failed to read stdin: {:?}", e);
+                        break;
+                    }
+                },
             }
         }
     });
+}
 
-    let task_run_peer_2 = tokio::task::spawn(async move {
-        loop {
-            peer2_emitter
-                .send(
-                    format!("{} Send regards", name_clone.clone())
-                        .as_bytes()
-                        .to_vec(),
-                )
-                .await
-                .expect("TODO: panic message");
-            tokio::time::sleep(std::time::Duration::from_millis(3000)).await;
+async fn print_messages(mut listener: mpsc::Receiver<NodeMessage>, workspace: Option<String>) {
+    let prefix = workspace.map(|w| format!("[{}] ", w)).unwrap_or_default();
+    while let Some(message) = listener.recv().await {
+        match message {
+            NodeMessage::Message {
+                time,
+                created_by,
+                data,
+                ..
+            } => {
+                println!(
+                    "{}{} {}: {}",
+                    prefix,
+                    format_time(time),
+                    created_by,
+                    String::from_utf8_lossy(&data)
+                );
+            }
+            NodeMessage::Event { time, event, .. } => {
+                println!("{}{} {:?}", prefix, format_time(time), event);
+            }
         }
-    });
+    }
+}
 
-    tokio::spawn(async {
-        task_peer_2.await.unwrap();
-        task_peer_2_listener.await.unwrap();
-        task_run_peer_2.await.unwrap();
-    })
+fn format_time(millis: u64) -> String {
+    chrono::DateTime::from_timestamp_millis(millis as i64)
+        .map(|t| t.format("%H:%M:%S%.3f").to_string())
+        .unwrap_or_else(|| millis.to_string())
+}
+
+fn fail(reason: &str) -> ! {
+    eprintln!("{}", reason);
+    std::process::exit(1)
 }
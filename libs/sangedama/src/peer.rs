@@ -2,3 +2,5 @@ mod behaviour;
 pub mod message;
 pub mod node;
 mod peer_swarm;
+
+pub use libp2p::{Multiaddr, PeerId};
@@ -3,4 +3,4 @@ mod client;
 mod server;
 pub use base::PeerBehaviour;
 pub use client::{ClientPeerBehaviour, ClientPeerEvent};
-pub use server::{PeerAdminBehaviour, PeerAdminEvent};
+pub use server::{PeerAdminBehaviour, PeerAdminEvent, MIN_REGISTRATION_TTL};
@@ -3,6 +3,7 @@ use std::time::Duration;
 
 use libp2p::gossipsub::{self, Config};
 use libp2p::swarm::NetworkBehaviour;
+use libp2p::{kad, StreamProtocol};
 use tokio::io;
 
 pub trait PeerBehaviour
@@ -24,8 +25,16 @@ pub fn create_gossip_sub_config() -> Config {
         .history_gossip(10)
         .heartbeat_interval(Duration::from_secs(1)) // This is set to aid debugging by not cluttering the log space
         .validation_mode(gossipsub::ValidationMode::Strict) // This sets the kind of message validation. The default is Strict (enforce message signing)
+        .validate_messages() // Messages are only forwarded once the application reported them valid
         .message_id_fn(message_id_fn) // content-address messages. No two messages of the same content will be propagated.
         .build()
-        .map_err(|msg| io::Error::new(io::ErrorKind::Other, msg))
+        .map_err(io::Error::other)
         .unwrap()
 }
+
+pub fn create_kad(key: &libp2p::identity::Keypair) -> kad::Behaviour<kad::store::MemoryStore> {
+    let peer_id = key.public().to_peer_id();
+    let mut config = kad::Config::default();
+    config.set_protocol_names(vec![StreamProtocol::new("/CEYLON-AI-KAD/0.0.1")]);
+    kad::Behaviour::with_config(peer_id, kad::store::MemoryStore::new(peer_id), config)
+}
@@ -1,10 +1,14 @@
 use std::time::Duration;
 
+use libp2p::request_response::{self, ProtocolSupport};
 use libp2p::swarm::NetworkBehaviour;
-use libp2p::{gossipsub, identify, identity, mdns, ping, rendezvous};
+use libp2p::{gossipsub, identify, identity, kad, mdns, ping, rendezvous, StreamProtocol};
 
-use crate::peer::behaviour::base::create_gossip_sub_config;
+use crate::peer::behaviour::base::{create_gossip_sub_config, create_kad};
 use crate::peer::behaviour::PeerBehaviour;
+use crate::peer::message::compression::agent_version;
+use crate::peer::message::direct::{DirectRequest, DirectResponse, DIRECT_PROTOCOL};
+use crate::peer::message::history::{HistoryRequest, HistoryResponse, HISTORY_PROTOCOL};
 
 // We create a custom network behaviour that combines Gossipsub and Mdns.
 #[derive(NetworkBehaviour)]
@@ -14,6 +18,9 @@ pub struct ClientPeerBehaviour {
     pub rendezvous: rendezvous::client::Behaviour,
     pub ping: ping::Behaviour,
     pub gossip_sub: gossipsub::Behaviour,
+    pub history: request_response::json::Behaviour<HistoryRequest, HistoryResponse>,
+    pub direct: request_response::json::Behaviour<DirectRequest, DirectResponse>,
+    pub kad: kad::Behaviour<kad::store::MemoryStore>,
 }
 
 #[derive(Debug)]
@@ -23,6 +30,9 @@ pub enum ClientPeerEvent {
     Ping(ping::Event),
     Identify(identify::Event),
     Rendezvous(rendezvous::client::Event),
+    History(request_response::Event<HistoryRequest, HistoryResponse>),
+    Direct(request_response::Event<DirectRequest, DirectResponse>),
+    Kad(kad::Event),
 }
 
 impl From<gossipsub::Event> for ClientPeerEvent {
@@ -54,6 +64,24 @@ impl From<identify::Event> for ClientPeerEvent {
     }
 }
 
+impl From<kad::Event> for ClientPeerEvent {
+    fn from(event: kad::Event) -> Self {
+        ClientPeerEvent::Kad(event)
+    }
+}
+
+impl From<request_response::Event<HistoryRequest, HistoryResponse>> for ClientPeerEvent {
+    fn from(event: request_response::Event<HistoryRequest, HistoryResponse>) -> Self {
+        ClientPeerEvent::History(event)
+    }
+}
+
+impl From<request_response::Event<DirectRequest, DirectResponse>> for ClientPeerEvent {
+    fn from(event: request_response::Event<DirectRequest, DirectResponse>) -> Self {
+        ClientPeerEvent::Direct(event)
+    }
+}
+
 impl PeerBehaviour for ClientPeerBehaviour {
     fn new(local_public_key: identity::Keypair) -> Self {
         // Set a custom gossip_sub_config configuration
@@ -66,12 +94,27 @@ impl PeerBehaviour for ClientPeerBehaviour {
 
         Self {
             gossip_sub,
-            identify: identify::Behaviour::new(identify::Config::new(
-                "/CEYLON-AI-IDENTITY/0.0.1".to_string(),
-                local_public_key.public(),
-            )),
+            identify: identify::Behaviour::new(
+                identify::Config::new(
+                    "/CEYLON-AI-IDENTITY/0.0.1".to_string(),
+                    local_public_key.public(),
+                )
+                .with_agent_version(agent_version()),
+            ),
             rendezvous: rendezvous::client::Behaviour::new(local_public_key.clone()),
             ping: ping::Behaviour::new(ping::Config::new().with_interval(Duration::from_secs(10))),
+            history: request_response::json::Behaviour::new(
+                [(
+                    StreamProtocol::new(HISTORY_PROTOCOL),
+                    ProtocolSupport::Outbound,
+                )],
+                request_response::Config::default(),
+            ),
+            direct: request_response::json::Behaviour::new(
+                [(StreamProtocol::new(DIRECT_PROTOCOL), ProtocolSupport::Full)],
+                request_response::Config::default(),
+            ),
+            kad: create_kad(&local_public_key),
         }
     }
 }
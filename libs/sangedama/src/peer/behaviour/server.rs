@@ -1,9 +1,20 @@
 use std::time::Duration;
 
+use libp2p::allow_block_list::{self, BlockedPeers};
+use libp2p::request_response::{self, ProtocolSupport};
 use libp2p::swarm::NetworkBehaviour;
-use libp2p::{gossipsub, identify, ping, rendezvous};
+use libp2p::{gossipsub, identify, kad, ping, rendezvous, StreamProtocol};
 
-use crate::peer::behaviour::{base::create_gossip_sub_config, PeerBehaviour};
+use crate::peer::behaviour::{
+    base::{create_gossip_sub_config, create_kad},
+    PeerBehaviour,
+};
+use crate::peer::message::compression::agent_version;
+use crate::peer::message::direct::{DirectRequest, DirectResponse, DIRECT_PROTOCOL};
+use crate::peer::message::history::{HistoryRequest, HistoryResponse, HISTORY_PROTOCOL};
+
+/// Shortest registration ttl in seconds accepted from members.
+pub const MIN_REGISTRATION_TTL: u64 = 10;
 
 // We create a custom network behaviour that combines Gossipsub and Mdns.
 #[derive(NetworkBehaviour)]
@@ -13,6 +24,10 @@ pub struct PeerAdminBehaviour {
     pub ping: ping::Behaviour,
     pub identify: identify::Behaviour,
     pub gossip_sub: gossipsub::Behaviour,
+    pub history: request_response::json::Behaviour<HistoryRequest, HistoryResponse>,
+    pub direct: request_response::json::Behaviour<DirectRequest, DirectResponse>,
+    pub kad: kad::Behaviour<kad::store::MemoryStore>,
+    pub blocked_peers: allow_block_list::Behaviour<BlockedPeers>,
 }
 
 #[derive(Debug)]
@@ -21,6 +36,9 @@ pub enum PeerAdminEvent {
     Ping(ping::Event),
     Identify(identify::Event),
     GossipSub(gossipsub::Event),
+    History(request_response::Event<HistoryRequest, HistoryResponse>),
+    Direct(request_response::Event<DirectRequest, DirectResponse>),
+    Kad(kad::Event),
 }
 
 impl From<gossipsub::Event> for PeerAdminEvent {
@@ -46,10 +64,36 @@ impl From<identify::Event> for PeerAdminEvent {
     }
 }
 
+impl From<kad::Event> for PeerAdminEvent {
+    fn from(event: kad::Event) -> Self {
+        PeerAdminEvent::Kad(event)
+    }
+}
+
+impl From<request_response::Event<HistoryRequest, HistoryResponse>> for PeerAdminEvent {
+    fn from(event: request_response::Event<HistoryRequest, HistoryResponse>) -> Self {
+        PeerAdminEvent::History(event)
+    }
+}
+
+impl From<request_response::Event<DirectRequest, DirectResponse>> for PeerAdminEvent {
+    fn from(event: request_response::Event<DirectRequest, DirectResponse>) -> Self {
+        PeerAdminEvent::Direct(event)
+    }
+}
+
+// `blocked_peers` never emits, its event type is `void::Void` in this libp2p version.
+impl From<void::Void> for PeerAdminEvent {
+    fn from(event: void::Void) -> Self {
+        void::unreachable(event)
+    }
+}
+
 impl PeerBehaviour for PeerAdminBehaviour {
     fn new(local_public_key: libp2p::identity::Keypair) -> Self {
-        let rendezvous_server =
-            rendezvous::server::Behaviour::new(rendezvous::server::Config::default());
+        let rendezvous_server = rendezvous::server::Behaviour::new(
+            rendezvous::server::Config::default().with_min_ttl(MIN_REGISTRATION_TTL),
+        );
         let gossip_sub_config = create_gossip_sub_config();
         let gossip_sub = gossipsub::Behaviour::new(
             gossipsub::MessageAuthenticity::Signed(local_public_key.clone()),
@@ -61,10 +105,26 @@ impl PeerBehaviour for PeerAdminBehaviour {
             gossip_sub,
             rendezvous: rendezvous_server,
             ping: ping::Behaviour::new(ping::Config::new().with_interval(Duration::from_secs(10))),
-            identify: identify::Behaviour::new(identify::Config::new(
-                "/CEYLON-AI-IDENTITY/0.0.1".to_string(),
-                local_public_key.public(),
-            )),
+            identify: identify::Behaviour::new(
+                identify::Config::new(
+                    "/CEYLON-AI-IDENTITY/0.0.1".to_string(),
+                    local_public_key.public(),
+                )
+                .with_agent_version(agent_version()),
+            ),
+            history: request_response::json::Behaviour::new(
+                [(
+                    StreamProtocol::new(HISTORY_PROTOCOL),
+                    ProtocolSupport::Inbound,
+                )],
+                request_response::Config::default(),
+            ),
+            direct: request_response::json::Behaviour::new(
+                [(StreamProtocol::new(DIRECT_PROTOCOL), ProtocolSupport::Full)],
+                request_response::Config::default(),
+            ),
+            kad: create_kad(&local_public_key),
+            blocked_peers: allow_block_list::Behaviour::default(),
         }
     }
 }
@@ -1 +1,8 @@
+pub mod clock;
+pub mod compression;
 pub mod data;
+pub mod direct;
+pub mod history;
+pub mod recording;
+pub mod trace;
+pub mod validation;
@@ -0,0 +1,117 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+pub fn unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+/// A hybrid logical clock stamp, physical milliseconds plus a logical counter
+/// that orders events sharing the same millisecond. Stamps order causally:
+/// whatever a node sends after receiving a message carries a larger stamp.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct HybridTimestamp {
+    pub wall_time: u64,
+    pub logical: u32,
+}
+
+impl HybridTimestamp {
+    pub fn new(wall_time: u64, logical: u32) -> Self {
+        Self { wall_time, logical }
+    }
+
+    pub fn happened_before(&self, other: &HybridTimestamp) -> bool {
+        self.cmp(other) == Ordering::Less
+    }
+
+    /// The later of the two stamps.
+    pub fn merge(&self, other: &HybridTimestamp) -> HybridTimestamp {
+        *self.max(other)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct HybridClock {
+    last: HybridTimestamp,
+}
+
+impl HybridClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn last(&self) -> HybridTimestamp {
+        self.last
+    }
+
+    /// Stamp for a local event or an outgoing message.
+    pub fn now(&mut self) -> HybridTimestamp {
+        let wall_time = unix_millis();
+        self.last = if wall_time > self.last.wall_time {
+            HybridTimestamp::new(wall_time, 0)
+        } else {
+            HybridTimestamp::new(self.last.wall_time, self.last.logical + 1)
+        };
+        self.last
+    }
+
+    /// Folds a received stamp into the clock, so later local stamps come after it.
+    pub fn update(&mut self, remote: HybridTimestamp) -> HybridTimestamp {
+        let wall_time = unix_millis();
+        let max_wall = wall_time.max(self.last.wall_time).max(remote.wall_time);
+        let logical = if max_wall == self.last.wall_time && max_wall == remote.wall_time {
+            self.last.logical.max(remote.logical) + 1
+        } else if max_wall == self.last.wall_time {
+            self.last.logical + 1
+        } else if max_wall == remote.wall_time {
+            remote.logical + 1
+        } else {
+            0
+        };
+        self.last = HybridTimestamp::new(max_wall, logical);
+        self.last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn now_is_monotonic() {
+        let mut clock = HybridClock::new();
+        let mut last = clock.now();
+        for _ in 0..1000 {
+            let next = clock.now();
+            assert!(last.happened_before(&next));
+            last = next;
+        }
+        assert_eq!(clock.last(), last);
+    }
+
+    #[test]
+    fn update_orders_after_remote_stamp() {
+        let mut clock = HybridClock::new();
+        let local = clock.now();
+        let remote = HybridTimestamp::new(local.wall_time + 60_000, 7);
+
+        let merged = clock.update(remote);
+        assert_eq!(merged, HybridTimestamp::new(remote.wall_time, 8));
+        assert!(remote.happened_before(&clock.now()));
+    }
+
+    #[test]
+    fn update_keeps_local_stamp_ahead_of_older_remote() {
+        let mut clock = HybridClock::new();
+        let local = clock.now();
+
+        let merged = clock.update(HybridTimestamp::new(1, 0));
+        assert!(local.happened_before(&merged));
+        assert_eq!(local.merge(&merged), merged);
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashSet;
+use std::io;
+
+use libp2p::gossipsub::{self, TopicHash};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+/// Largest payload a compressed message may expand to, anything bigger is treated as malicious.
+const MAX_PAYLOAD_SIZE: usize = 16 * 1024 * 1024;
+
+/// Token in the identify agent version of peers that read compressed messages.
+const CAPABILITY: &str = "compression/1";
+
+/// Agent version every peer advertises over identify.
+pub(crate) fn agent_version() -> String {
+    format!("sangedama/{} {}", env!("CARGO_PKG_VERSION"), CAPABILITY)
+}
+
+/// Algorithm a message payload was compressed with, recorded in the message envelope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Compression {
+    Zstd,
+    Lz4,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompressionConfig {
+    pub algorithm: Compression,
+    /// Payloads smaller than this many bytes are sent as they are.
+    pub threshold: usize,
+    /// Only used by zstd.
+    pub level: i32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: Compression::Zstd,
+            threshold: 1024,
+            level: 3,
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Compresses the payload if it is large enough and actually shrinks.
+    pub(crate) fn compress(&self, data: &[u8]) -> Option<Vec<u8>> {
+        if data.len() < self.threshold {
+            return None;
+        }
+        let compressed = match self.algorithm {
+            Compression::Zstd => zstd::bulk::compress(data, self.level).ok()?,
+            Compression::Lz4 => lz4_flex::compress_prepend_size(data),
+        };
+        (compressed.len() < data.len()).then_some(compressed)
+    }
+}
+
+impl Compression {
+    pub(crate) fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::Zstd => zstd::bulk::decompress(data, MAX_PAYLOAD_SIZE),
+            Compression::Lz4 => {
+                let size = data
+                    .get(..4)
+                    .map(|size| u32::from_le_bytes(size.try_into().unwrap()) as usize);
+                if size.is_none_or(|size| size > MAX_PAYLOAD_SIZE) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "lz4 payload too large",
+                    ));
+                }
+                lz4_flex::decompress_size_prepended(data)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+        }
+    }
+}
+
+/// Peers that advertised they read compressed messages.
+/// Older peers would take the compressed bytes for the payload, so nothing is compressed
+/// for a topic until every peer known to be on it has identified as capable.
+#[derive(Debug, Default)]
+pub(crate) struct CompressionPeers {
+    peers: HashSet<PeerId>,
+}
+
+impl CompressionPeers {
+    pub fn identified(&mut self, peer_id: PeerId, agent_version: &str) {
+        if agent_version
+            .split_whitespace()
+            .any(|token| token == CAPABILITY)
+        {
+            self.peers.insert(peer_id);
+        } else {
+            self.peers.remove(&peer_id);
+        }
+    }
+
+    pub fn forget(&mut self, peer_id: &PeerId) {
+        self.peers.remove(peer_id);
+    }
+
+    /// The config to publish on `topic` with, `None` while a subscriber may not read it.
+    pub fn config_for<'a>(
+        &self,
+        config: Option<&'a CompressionConfig>,
+        gossip_sub: &gossipsub::Behaviour,
+        topic: &TopicHash,
+    ) -> Option<&'a CompressionConfig> {
+        let config = config?;
+        gossip_sub
+            .all_peers()
+            .filter(|(_, topics)| topics.contains(&topic))
+            .all(|(peer, _)| self.peers.contains(peer))
+            .then_some(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> Vec<u8> {
+        b"ceylon workspace payload ".repeat(200)
+    }
+
+    #[test]
+    fn round_trips_both_algorithms() {
+        for algorithm in [Compression::Zstd, Compression::Lz4] {
+            let config = CompressionConfig {
+                algorithm,
+                ..CompressionConfig::default()
+            };
+            let compressed = config.compress(&payload()).unwrap();
+            assert!(compressed.len() < payload().len());
+            assert_eq!(algorithm.decompress(&compressed).unwrap(), payload());
+        }
+    }
+
+    #[test]
+    fn small_or_incompressible_payloads_are_left_alone() {
+        let config = CompressionConfig::default();
+        assert!(config
+            .compress(&payload()[..config.threshold - 1])
+            .is_none());
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let noise: Vec<u8> = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 56) as u8
+            })
+            .collect();
+        assert!(config.compress(&noise).is_none());
+    }
+
+    #[test]
+    fn lz4_rejects_oversized_or_missing_size_prefix() {
+        let mut forged = ((MAX_PAYLOAD_SIZE + 1) as u32).to_le_bytes().to_vec();
+        forged.extend_from_slice(&[0; 16]);
+        assert!(Compression::Lz4.decompress(&forged).is_err());
+        assert!(Compression::Lz4.decompress(&[1, 2]).is_err());
+    }
+
+    #[test]
+    fn only_capable_agents_are_tracked() {
+        let mut peers = CompressionPeers::default();
+        let capable = PeerId::random();
+        let old = PeerId::random();
+        peers.identified(capable, &agent_version());
+        peers.identified(old, "rust-libp2p/0.44.2");
+        assert!(peers.peers.contains(&capable));
+        assert!(!peers.peers.contains(&old));
+        peers.forget(&capable);
+        assert!(peers.peers.is_empty());
+    }
+}
@@ -1,29 +1,249 @@
+use std::time::Duration;
+
+use libp2p::PeerId;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-#[derive(Debug, Serialize, Deserialize)]
+use tracing::{info_span, Span};
+
+use crate::peer::message::clock::{unix_millis, HybridClock, HybridTimestamp};
+use crate::peer::message::compression::{Compression, CompressionConfig};
+use crate::peer::message::trace::TraceContext;
+
+/// Default allowance for clocks of sender and receiver disagreeing when checking expiry.
+pub const DEFAULT_CLOCK_SKEW: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum EventType {
-    Subscribe { topic: String, peer_id: String },
-    Unsubscribe { topic: String, peer_id: String },
+    Subscribe {
+        topic: String,
+        peer_id: String,
+    },
+    Unsubscribe {
+        topic: String,
+        peer_id: String,
+    },
+    AdminChanged {
+        peer_id: String,
+    },
+    PeerGraylisted {
+        peer_id: String,
+        score: f64,
+    },
+    PeersDiscovered {
+        peers: Vec<String>,
+    },
+    PeerKicked {
+        peer_id: String,
+    },
+    /// `until` is the unix time in milliseconds the ban ends, `None` for a permanent ban.
+    PeerBanned {
+        peer_id: String,
+        until: Option<u64>,
+    },
+    PeerUnbanned {
+        peer_id: String,
+    },
+    RegistrationFailed {
+        namespace: String,
+        error: String,
+    },
+    /// The peer left the workspace on purpose.
+    AgentLeft {
+        peer_id: String,
+    },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum NodeMessage {
     Event {
+        /// Unix time in milliseconds.
         time: u64,
         created_by: String,
         event: EventType,
+        #[serde(default)]
+        hlc: HybridTimestamp,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        trace: Option<TraceContext>,
     },
     Message {
+        /// Unix time in milliseconds.
         time: u64,
         created_by: String,
         data: Vec<u8>,
+        #[serde(default)]
+        hlc: HybridTimestamp,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        trace: Option<TraceContext>,
+        /// Set on the wire when `data` is compressed, parsed messages always hold the plain payload.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        compression: Option<Compression>,
+        /// Milliseconds after `time` the message goes stale, `None` never.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ttl: Option<u64>,
     },
 }
 
+/// Lane an outgoing message is queued and published in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Priority {
+    /// Urgent commands like stop or reassign, sent before any queued data.
+    Control,
+    #[default]
+    Data,
+}
+
+/// A payload handed to a peer to broadcast.
+#[derive(Debug, Clone)]
+pub struct OutgoingMessage {
+    pub data: Vec<u8>,
+    /// How long receivers may act on the message, `None` for the workspace default.
+    pub ttl: Option<Duration>,
+    pub priority: Priority,
+    /// Trace context of the span the message was queued in, captured on creation.
+    pub trace: Option<TraceContext>,
+    /// The one peer the message is for, sent to it alone instead of gossiped.
+    pub to: Option<PeerId>,
+}
+
+impl OutgoingMessage {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self {
+            data,
+            ttl: None,
+            priority: Priority::Data,
+            trace: TraceContext::current(),
+            to: None,
+        }
+    }
+
+    pub fn with_ttl(data: Vec<u8>, ttl: Duration) -> Self {
+        Self {
+            ttl: Some(ttl),
+            ..Self::new(data)
+        }
+    }
+
+    pub fn control(data: Vec<u8>) -> Self {
+        Self {
+            priority: Priority::Control,
+            ..Self::new(data)
+        }
+    }
+
+    /// Span publishing the message, a child of the span it was queued in.
+    pub(crate) fn broadcast_span(&self, peer: &str, topic: &str) -> Span {
+        let span = info_span!("broadcast", peer = %peer, topic = %topic);
+        if let Some(trace) = &self.trace {
+            trace.attach(&span);
+        }
+        span
+    }
+}
+
+impl From<Vec<u8>> for OutgoingMessage {
+    fn from(data: Vec<u8>) -> Self {
+        Self::new(data)
+    }
+}
+
 impl NodeMessage {
+    pub fn new_event(clock: &mut HybridClock, created_by: String, event: EventType) -> Self {
+        NodeMessage::Event {
+            time: unix_millis(),
+            created_by,
+            event,
+            hlc: clock.now(),
+            trace: TraceContext::current(),
+        }
+    }
+
+    pub fn new_message(clock: &mut HybridClock, created_by: String, data: Vec<u8>) -> Self {
+        NodeMessage::Message {
+            time: unix_millis(),
+            created_by,
+            data,
+            hlc: clock.now(),
+            trace: TraceContext::current(),
+            compression: None,
+            ttl: None,
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Option<Duration>) -> Self {
+        if let NodeMessage::Message {
+            ttl: message_ttl, ..
+        } = &mut self
+        {
+            *message_ttl = ttl.map(|ttl| ttl.as_millis() as u64);
+        }
+        self
+    }
+
+    /// Whether the message went stale by `now`, in unix milliseconds.
+    /// `clock_skew` is added to the ttl, so a receiver whose clock runs ahead of the
+    /// sender's does not drop messages that are still fresh.
+    pub fn is_expired(&self, now: u64, clock_skew: Duration) -> bool {
+        match self {
+            NodeMessage::Message {
+                time,
+                ttl: Some(ttl),
+                ..
+            } => {
+                time.saturating_add(*ttl)
+                    .saturating_add(clock_skew.as_millis() as u64)
+                    <= now
+            }
+            _ => false,
+        }
+    }
+
+    pub fn hlc(&self) -> HybridTimestamp {
+        match self {
+            NodeMessage::Event { hlc, .. } | NodeMessage::Message { hlc, .. } => *hlc,
+        }
+    }
+
+    pub fn trace(&self) -> Option<&TraceContext> {
+        match self {
+            NodeMessage::Event { trace, .. } | NodeMessage::Message { trace, .. } => trace.as_ref(),
+        }
+    }
+
+    pub fn created_by(&self) -> &str {
+        match self {
+            NodeMessage::Event { created_by, .. } | NodeMessage::Message { created_by, .. } => {
+                created_by
+            }
+        }
+    }
+
+    /// Span for handling this message, continuing the sender's trace when it carries one.
+    pub fn handling_span(&self) -> Span {
+        let span = info_span!("handle_message", created_by = %self.created_by());
+        if let Some(trace) = self.trace() {
+            trace.attach(&span);
+        }
+        span
+    }
+
     pub fn from_bytes(bytes: Vec<u8>) -> Self {
-        serde_json::from_slice(&bytes).unwrap()
+        Self::try_from_bytes(&bytes).unwrap()
+    }
+
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, serde_json::Error> {
+        let mut message: Self = serde_json::from_slice(bytes)?;
+        if let NodeMessage::Message {
+            data, compression, ..
+        } = &mut message
+        {
+            if let Some(algorithm) = compression.take() {
+                *data = algorithm
+                    .decompress(data)
+                    .map_err(serde::de::Error::custom)?;
+            }
+        }
+        Ok(message)
     }
     pub fn to_json(&self) -> String {
         json!(self).to_string()
@@ -32,4 +252,82 @@ impl NodeMessage {
     pub fn to_bytes(&self) -> Vec<u8> {
         serde_json::to_vec(self).unwrap()
     }
+
+    /// Like [`NodeMessage::to_bytes`], compressing the payload when the config says so.
+    /// Peers that do not compress themselves still read these messages, peers older than
+    /// compression do not, see [`CompressionPeers`](super::compression::CompressionPeers).
+    pub fn to_compressed_bytes(&self, compression: Option<&CompressionConfig>) -> Vec<u8> {
+        let Some(config) = compression else {
+            return self.to_bytes();
+        };
+        let NodeMessage::Message { data, .. } = self else {
+            return self.to_bytes();
+        };
+        let Some(compressed) = config.compress(data) else {
+            return self.to_bytes();
+        };
+        let mut message = self.clone();
+        if let NodeMessage::Message {
+            data, compression, ..
+        } = &mut message
+        {
+            *data = compressed;
+            *compression = Some(config.algorithm);
+        }
+        message.to_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressed_message_reads_back_plain() {
+        let payload = b"ceylon workspace payload ".repeat(200);
+        let message =
+            NodeMessage::new_message(&mut HybridClock::new(), "a".into(), payload.clone());
+        let bytes = message.to_compressed_bytes(Some(&CompressionConfig::default()));
+        assert!(bytes.len() < message.to_bytes().len());
+        match NodeMessage::try_from_bytes(&bytes).unwrap() {
+            NodeMessage::Message {
+                data, compression, ..
+            } => {
+                assert_eq!(data, payload);
+                assert_eq!(compression, None);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    fn message_with_ttl(time: u64, ttl: Option<u64>) -> NodeMessage {
+        let mut message = NodeMessage::new_message(&mut HybridClock::new(), "a".into(), vec![]);
+        if let NodeMessage::Message {
+            time: message_time,
+            ttl: message_ttl,
+            ..
+        } = &mut message
+        {
+            *message_time = time;
+            *message_ttl = ttl;
+        }
+        message
+    }
+
+    #[test]
+    fn expires_once_ttl_and_skew_have_passed() {
+        let message = message_with_ttl(10_000, Some(500));
+        assert!(!message.is_expired(10_499, Duration::ZERO));
+        assert!(message.is_expired(10_500, Duration::ZERO));
+        assert!(!message.is_expired(10_500, Duration::from_millis(200)));
+        assert!(message.is_expired(10_700, Duration::from_millis(200)));
+    }
+
+    #[test]
+    fn messages_without_ttl_never_expire() {
+        let message = message_with_ttl(0, None);
+        assert!(!message.is_expired(u64::MAX, Duration::ZERO));
+        let message = message_with_ttl(u64::MAX, Some(u64::MAX));
+        assert!(!message.is_expired(u64::MAX - 1, Duration::ZERO));
+    }
 }
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+/// Protocol messages addressed to a single peer travel on instead of being gossiped, so only
+/// the recipient validates, records and handles them and no history keeps them.
+/// Members reach each other through their admin, which relays without looking at them.
+pub const DIRECT_PROTOCOL: &str = "/CEYLON-AI-DIRECT/0.0.1";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectRequest {
+    pub topic: String,
+    /// Peer id of the author, the requester itself unless an admin relays the message.
+    pub from: String,
+    pub to: String,
+    /// The message as [`NodeMessage::to_bytes`](crate::peer::message::data::NodeMessage::to_bytes)
+    /// encodes it.
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectResponse {
+    /// Whether the message was handed to the recipient, or to the admin relaying it.
+    pub accepted: bool,
+}
@@ -0,0 +1,203 @@
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::peer::message::clock::unix_millis;
+use crate::peer::message::data::NodeMessage;
+
+/// 0.0.2 stamps entries and messages with unix milliseconds where 0.0.1 used seconds,
+/// so peers of either version do not exchange history with each other.
+pub const HISTORY_PROTOCOL: &str = "/CEYLON-AI-HISTORY/0.0.2";
+
+#[derive(Debug, Clone)]
+pub struct HistoryConfig {
+    /// Maximum number of messages kept for replay, `0` disables the history.
+    pub capacity: usize,
+    /// Optional JSON lines file the history is appended to and restored from.
+    pub path: Option<PathBuf>,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 100,
+            path: None,
+        }
+    }
+}
+
+/// Where a replay should start from.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum HistoryCursor {
+    /// Every message still retained.
+    Beginning,
+    /// Messages with a sequence number greater than the given one.
+    Sequence(u64),
+    /// Messages stored at or after the given unix time in milliseconds.
+    Time(u64),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub seq: u64,
+    pub time: u64,
+    pub topic: String,
+    pub message: NodeMessage,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryRequest {
+    pub topic: String,
+    pub since: HistoryCursor,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryResponse {
+    pub entries: Vec<HistoryEntry>,
+}
+
+pub struct MessageHistory {
+    config: HistoryConfig,
+    entries: VecDeque<HistoryEntry>,
+    next_seq: u64,
+    file: Option<File>,
+    /// Lines in the history file, compacted back to `capacity` once it doubles.
+    lines: usize,
+}
+
+impl MessageHistory {
+    pub fn new(config: HistoryConfig) -> Self {
+        let mut entries = VecDeque::new();
+        let mut file = None;
+
+        if let Some(path) = &config.path {
+            if let Ok(existing) = File::open(path) {
+                for line in BufReader::new(existing).lines().map_while(Result::ok) {
+                    if let Ok(entry) = serde_json::from_str::<HistoryEntry>(&line) {
+                        entries.push_back(entry);
+                        if entries.len() > config.capacity {
+                            entries.pop_front();
+                        }
+                    }
+                }
+            }
+            file = compact(path, &entries);
+        }
+        let next_seq = entries.back().map(|e| e.seq + 1).unwrap_or(0);
+
+        let lines = entries.len();
+        Self {
+            config,
+            entries,
+            next_seq,
+            file,
+            lines,
+        }
+    }
+
+    pub fn push(&mut self, topic: String, message: NodeMessage) -> Option<u64> {
+        if self.config.capacity == 0 {
+            return None;
+        }
+        let entry = HistoryEntry {
+            seq: self.next_seq,
+            time: unix_millis(),
+            topic,
+            message,
+        };
+        self.next_seq += 1;
+
+        if let Some(file) = self.file.as_mut() {
+            if let Err(e) = writeln!(file, "{}", serde_json::to_string(&entry).unwrap()) {
+                error!("Failed to persist history entry {}: {:?}", entry.seq, e);
+            }
+            self.lines += 1;
+        }
+
+        let seq = entry.seq;
+        self.entries.push_back(entry);
+        while self.entries.len() > self.config.capacity {
+            self.entries.pop_front();
+        }
+        if self.lines > self.config.capacity * 2 {
+            if let Some(path) = &self.config.path {
+                self.file = compact(path, &self.entries);
+                self.lines = self.entries.len();
+            }
+        }
+        Some(seq)
+    }
+
+    pub fn since(&self, topic: &str, cursor: HistoryCursor) -> Vec<HistoryEntry> {
+        self.entries
+            .iter()
+            .filter(|e| e.topic == topic)
+            .filter(|e| match cursor {
+                HistoryCursor::Beginning => true,
+                HistoryCursor::Sequence(seq) => e.seq > seq,
+                HistoryCursor::Time(time) => e.time >= time,
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// Rewrites the history file with only the retained entries and reopens it for appending.
+fn compact(path: &Path, entries: &VecDeque<HistoryEntry>) -> Option<File> {
+    let tmp = path.with_extension("compact");
+    let written = File::create(&tmp).and_then(|mut f| {
+        for entry in entries {
+            writeln!(f, "{}", serde_json::to_string(entry).unwrap())?;
+        }
+        f.sync_all()
+    });
+    if let Err(e) = written.and_then(|_| std::fs::rename(&tmp, path)) {
+        error!("Failed to compact history file {:?}: {:?}", path, e);
+    }
+    match OpenOptions::new().create(true).append(true).open(path) {
+        Ok(f) => Some(f),
+        Err(e) => {
+            error!("Failed to open history file {:?}: {:?}", path, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peer::message::clock::HybridClock;
+
+    #[test]
+    fn history_file_is_compacted_to_capacity() {
+        let path = std::env::temp_dir().join(format!("history-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let config = HistoryConfig {
+            capacity: 3,
+            path: Some(path.clone()),
+        };
+        let mut clock = HybridClock::new();
+
+        let mut history = MessageHistory::new(config.clone());
+        for i in 0..10u8 {
+            let message = NodeMessage::new_message(&mut clock, "peer".to_string(), vec![i]);
+            history.push("topic".to_string(), message);
+        }
+        let lines = std::fs::read_to_string(&path).unwrap().lines().count();
+        assert!(lines <= config.capacity * 2, "{} lines", lines);
+
+        let restored = MessageHistory::new(config.clone());
+        let seqs: Vec<u64> = restored
+            .since("topic", HistoryCursor::Beginning)
+            .iter()
+            .map(|e| e.seq)
+            .collect();
+        assert_eq!(seqs, vec![7, 8, 9]);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 3);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
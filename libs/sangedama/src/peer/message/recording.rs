@@ -0,0 +1,195 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc as std_mpsc, Arc};
+use std::thread::JoinHandle;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::error;
+
+use crate::peer::message::clock::unix_millis;
+use crate::peer::message::data::NodeMessage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    /// Published by the recording node.
+    Sent,
+    /// Handed to the application of the recording node.
+    Received,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    pub time: u64,
+    pub direction: Direction,
+    pub topic: String,
+    pub message: NodeMessage,
+}
+
+/// Appends the traffic of a node to a JSON lines file, cheap to clone.
+/// The file is written on a thread of its own, so recording never blocks the swarm and
+/// needs no runtime. The last clone dropped waits for what is queued to be written.
+#[derive(Debug, Clone)]
+pub struct MessageRecorder {
+    writer: Arc<Writer>,
+}
+
+#[derive(Debug)]
+struct Writer {
+    entries: Option<std_mpsc::Sender<RecordedMessage>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        self.entries.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl MessageRecorder {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let (entries, rx) = std_mpsc::channel();
+        let thread = std::thread::Builder::new()
+            .name("message-recorder".to_string())
+            .spawn(move || write_entries(BufWriter::new(file), rx))?;
+        Ok(Self {
+            writer: Arc::new(Writer {
+                entries: Some(entries),
+                thread: Some(thread),
+            }),
+        })
+    }
+
+    pub fn record(&self, direction: Direction, topic: &str, message: &NodeMessage) {
+        let entry = RecordedMessage {
+            time: unix_millis(),
+            direction,
+            topic: topic.to_string(),
+            message: message.clone(),
+        };
+        let Some(entries) = &self.writer.entries else {
+            return;
+        };
+        if entries.send(entry).is_err() {
+            error!("Failed to record message: recorder stopped");
+        }
+    }
+
+    /// A sender recording everything handed to the application before passing it on.
+    /// Forwards on a thread of its own, so it can be set up outside of a runtime.
+    pub(crate) fn tap(
+        &self,
+        topic: String,
+        outside_tx: mpsc::Sender<NodeMessage>,
+    ) -> mpsc::Sender<NodeMessage> {
+        let (tx, mut rx) = mpsc::channel::<NodeMessage>(outside_tx.max_capacity());
+        let recorder = self.clone();
+        let forward = move || {
+            while let Some(message) = rx.blocking_recv() {
+                recorder.record(Direction::Received, &topic, &message);
+                if outside_tx.blocking_send(message).is_err() {
+                    break;
+                }
+            }
+        };
+        if let Err(e) = std::thread::Builder::new()
+            .name("message-recorder-tap".to_string())
+            .spawn(forward)
+        {
+            error!("Failed to start recording received messages: {:?}", e);
+        }
+        tx
+    }
+}
+
+// Writes entries as they come, flushing whenever the queue runs empty.
+fn write_entries(mut file: BufWriter<File>, entries: std_mpsc::Receiver<RecordedMessage>) {
+    while let Ok(entry) = entries.recv() {
+        for entry in std::iter::once(entry).chain(entries.try_iter()) {
+            if let Err(e) = writeln!(file, "{}", serde_json::to_string(&entry).unwrap()) {
+                error!("Failed to record message: {:?}", e);
+            }
+        }
+        if let Err(e) = file.flush() {
+            error!("Failed to record message: {:?}", e);
+        }
+    }
+}
+
+pub(crate) fn open_recorder(path: Option<&PathBuf>) -> Option<MessageRecorder> {
+    let path = path?;
+    match MessageRecorder::open(path) {
+        Ok(recorder) => Some(recorder),
+        Err(e) => {
+            error!("Failed to open recording {:?}: {:?}", path, e);
+            None
+        }
+    }
+}
+
+/// Reads a recording back in the order it was written.
+pub fn read_recording(path: &Path) -> io::Result<Vec<RecordedMessage>> {
+    let file = File::open(path)?;
+    let mut entries = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        entries.push(serde_json::from_str(&line)?);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peer::message::clock::HybridClock;
+
+    #[test]
+    fn recording_reads_back_in_order() {
+        let path = std::env::temp_dir().join(format!("recording-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut clock = HybridClock::new();
+
+        let recorder = MessageRecorder::open(&path).unwrap();
+        // Set up without a runtime, the way bindings create their nodes.
+        let (outside_tx, mut outside_rx) = mpsc::channel(4);
+        let tapped = recorder.tap("topic".to_string(), outside_tx);
+        for i in 0..3u8 {
+            let message = NodeMessage::new_message(&mut clock, "peer".to_string(), vec![i]);
+            recorder.record(Direction::Sent, "topic", &message);
+        }
+        let received = NodeMessage::new_message(&mut clock, "other".to_string(), vec![9]);
+        tapped.blocking_send(received).unwrap();
+        assert!(outside_rx.blocking_recv().is_some());
+        drop(tapped);
+        drop(recorder);
+        // The tap thread may still hold a clone, wait for it to let go.
+        let mut entries = vec![];
+        for _ in 0..100 {
+            entries = read_recording(&path).unwrap();
+            if entries.len() == 4 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(entries.len(), 4);
+        for (i, entry) in entries[..3].iter().enumerate() {
+            assert_eq!(entry.direction, Direction::Sent);
+            assert_eq!(entry.topic, "topic");
+            match &entry.message {
+                NodeMessage::Message { data, .. } => assert_eq!(data, &vec![i as u8]),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        assert_eq!(entries[3].direction, Direction::Received);
+    }
+}
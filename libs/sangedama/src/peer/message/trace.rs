@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use serde::{Deserialize, Serialize};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+const TRACEPARENT: &str = "traceparent";
+const TRACESTATE: &str = "tracestate";
+
+/// W3C trace context carried by a message, so the receiver continues the sender's trace.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceContext {
+    pub traceparent: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracestate: Option<String>,
+}
+
+impl TraceContext {
+    /// Context of the current span, `None` when it is not recorded by an OpenTelemetry layer.
+    pub fn current() -> Option<Self> {
+        let context = Span::current().context();
+        if !context.span().span_context().is_valid() {
+            return None;
+        }
+        let mut carrier = HashMap::new();
+        TraceContextPropagator::new().inject_context(&context, &mut carrier);
+        Some(Self {
+            traceparent: carrier.remove(TRACEPARENT)?,
+            tracestate: carrier.remove(TRACESTATE).filter(|s| !s.is_empty()),
+        })
+    }
+
+    pub fn context(&self) -> opentelemetry::Context {
+        let mut carrier = HashMap::from([(TRACEPARENT.to_string(), self.traceparent.clone())]);
+        if let Some(tracestate) = &self.tracestate {
+            carrier.insert(TRACESTATE.to_string(), tracestate.clone());
+        }
+        TraceContextPropagator::new().extract(&carrier)
+    }
+
+    /// Makes `span` a child of the remote span this context was taken from.
+    pub fn attach(&self, span: &Span) {
+        span.set_parent(self.context());
+    }
+}
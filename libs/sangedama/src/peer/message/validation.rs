@@ -0,0 +1,226 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+pub use libp2p::gossipsub::MessageAcceptance;
+
+use libp2p::gossipsub::{self, MessageId};
+use libp2p::PeerId;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tracing::{debug, error, warn};
+
+use crate::peer::message::clock::unix_millis;
+use crate::peer::message::data::NodeMessage;
+use crate::peer::node::PeerMetrics;
+
+/// Decides whether a workspace message is delivered and forwarded to other peers.
+/// Rejected messages are dropped and count against the peer that sent them.
+#[async_trait::async_trait]
+pub trait MessageValidator: Send + Sync {
+    async fn validate(&self, message: &NodeMessage) -> MessageAcceptance;
+}
+
+/// Parses a gossiped message and runs it through the validator, if any.
+/// Messages that do not parse or claim another author than their signed `source` are always
+/// rejected, expired ones ignored. Past this check `created_by` can be trusted.
+pub(crate) async fn validate_message(
+    validator: Option<&Arc<dyn MessageValidator>>,
+    source: Option<&PeerId>,
+    data: &[u8],
+    clock_skew: Duration,
+    metrics: &PeerMetrics,
+) -> (MessageAcceptance, Option<NodeMessage>) {
+    let message = match NodeMessage::try_from_bytes(data) {
+        Ok(message) => message,
+        Err(_) => return (MessageAcceptance::Reject, None),
+    };
+    if source.is_none_or(|source| source.to_string() != message.created_by()) {
+        warn!(
+            "Message from {:?} claims to be created by {}",
+            source,
+            message.created_by()
+        );
+        return (MessageAcceptance::Reject, None);
+    }
+    // Stale is not malicious, the message is just neither delivered nor forwarded.
+    if message.is_expired(unix_millis(), clock_skew) {
+        debug!("Dropping expired message {:?}", message);
+        metrics.count_expired_received();
+        return (MessageAcceptance::Ignore, None);
+    }
+    let acceptance = match validator {
+        Some(validator) => validator.validate(&message).await,
+        None => MessageAcceptance::Accept,
+    };
+    match acceptance {
+        MessageAcceptance::Accept => (acceptance, Some(message)),
+        _ => (acceptance, None),
+    }
+}
+
+/// Tells gossipsub whether to deliver and forward a message it holds back for validation.
+pub(crate) fn report_validation(
+    gossip_sub: &mut gossipsub::Behaviour,
+    message_id: &MessageId,
+    propagation_source: &PeerId,
+    acceptance: MessageAcceptance,
+) {
+    if !matches!(acceptance, MessageAcceptance::Accept) {
+        warn!(
+            "Message {} from {} not accepted: {:?}",
+            message_id, propagation_source, acceptance
+        );
+    }
+    if let Err(e) =
+        gossip_sub.report_message_validation_result(message_id, propagation_source, acceptance)
+    {
+        error!("Failed to report message validation: {:?}", e);
+    }
+}
+
+/// How many received messages may wait for their validator before new ones are ignored.
+const VALIDATION_QUEUE_CAPACITY: usize = 256;
+
+/// How a message waiting for its validator reached this peer.
+pub(crate) enum Route {
+    /// Gossiped, gossipsub holds it back until told whether to forward it.
+    Gossip {
+        message_id: MessageId,
+        propagation_source: PeerId,
+    },
+    /// Addressed to this peer alone, nobody waits for the verdict.
+    Direct,
+}
+
+impl Route {
+    /// Hands the verdict to gossipsub when it waits for one.
+    pub fn report(&self, gossip_sub: &mut gossipsub::Behaviour, acceptance: MessageAcceptance) {
+        match self {
+            Route::Gossip {
+                message_id,
+                propagation_source,
+            } => report_validation(gossip_sub, message_id, propagation_source, acceptance),
+            Route::Direct if !matches!(acceptance, MessageAcceptance::Accept) => {
+                warn!("Direct message not accepted: {:?}", acceptance);
+            }
+            Route::Direct => {}
+        }
+    }
+}
+
+/// A received message waiting for its validator. Direct messages carry their author as
+/// `source`, as gossiped ones do.
+pub(crate) struct PendingValidation {
+    pub route: Route,
+    pub message: gossipsub::Message,
+    pub validator: Option<Arc<dyn MessageValidator>>,
+}
+
+/// The verdict on a [`PendingValidation`], the parsed message is only set when accepted.
+pub(crate) struct Validated {
+    pub route: Route,
+    pub message: gossipsub::Message,
+    pub acceptance: MessageAcceptance,
+    pub node_message: Option<NodeMessage>,
+}
+
+/// Runs validators on a task of their own so a slow validator never holds up the swarm.
+/// Messages are validated one at a time, in the order they were received.
+pub(crate) struct ValidationQueue {
+    pending_tx: mpsc::Sender<PendingValidation>,
+    pending_rx: Option<mpsc::Receiver<PendingValidation>>,
+    validated_tx: mpsc::Sender<Validated>,
+    validated_rx: mpsc::Receiver<Validated>,
+}
+
+impl ValidationQueue {
+    pub fn new() -> Self {
+        let (pending_tx, pending_rx) = mpsc::channel(VALIDATION_QUEUE_CAPACITY);
+        let (validated_tx, validated_rx) = mpsc::channel(VALIDATION_QUEUE_CAPACITY);
+        Self {
+            pending_tx,
+            pending_rx: Some(pending_rx),
+            validated_tx,
+            validated_rx,
+        }
+    }
+
+    /// Spawns the validating task on the current runtime, once.
+    pub fn start(&mut self, metrics: Arc<PeerMetrics>, clock_skew: Duration) {
+        let Some(mut pending_rx) = self.pending_rx.take() else {
+            return;
+        };
+        let validated_tx = self.validated_tx.clone();
+        tokio::spawn(async move {
+            while let Some(pending) = pending_rx.recv().await {
+                let (acceptance, node_message) = validate_message(
+                    pending.validator.as_ref(),
+                    pending.message.source.as_ref(),
+                    &pending.message.data,
+                    clock_skew,
+                    &metrics,
+                )
+                .await;
+                let validated = Validated {
+                    route: pending.route,
+                    message: pending.message,
+                    acceptance,
+                    node_message,
+                };
+                if validated_tx.send(validated).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Queues a message for validation, handing it back when the queue is full.
+    pub fn submit(&self, pending: PendingValidation) -> Result<(), Box<PendingValidation>> {
+        self.pending_tx.try_send(pending).map_err(|e| match e {
+            TrySendError::Full(pending) | TrySendError::Closed(pending) => {
+                warn!("Validation queue full, ignoring message");
+                Box::new(pending)
+            }
+        })
+    }
+
+    pub async fn next(&mut self) -> Validated {
+        match self.validated_rx.recv().await {
+            Some(validated) => validated,
+            // The queue holds a sender itself, so this never ends.
+            None => std::future::pending().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peer::message::clock::HybridClock;
+
+    async fn acceptance(created_by: &PeerId, source: Option<&PeerId>) -> MessageAcceptance {
+        let message =
+            NodeMessage::new_message(&mut HybridClock::new(), created_by.to_string(), vec![]);
+        let metrics = PeerMetrics::default();
+        validate_message(None, source, &message.to_bytes(), Duration::ZERO, &metrics)
+            .await
+            .0
+    }
+
+    #[tokio::test]
+    async fn messages_must_be_created_by_their_source() {
+        let source = PeerId::random();
+        assert!(matches!(
+            acceptance(&source, Some(&source)).await,
+            MessageAcceptance::Accept
+        ));
+        assert!(matches!(
+            acceptance(&PeerId::random(), Some(&source)).await,
+            MessageAcceptance::Reject
+        ));
+        assert!(matches!(
+            acceptance(&source, None).await,
+            MessageAcceptance::Reject
+        ));
+    }
+}
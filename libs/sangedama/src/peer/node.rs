@@ -1,8 +1,34 @@
 mod admin;
+mod discovery;
+mod events;
+mod failover;
+mod faults;
+mod lanes;
 mod member;
+mod metrics;
+mod moderation;
 mod peer_builder;
+mod scoring;
+mod shutdown;
+#[cfg(test)]
+mod test_support;
+mod workspace;
 
 pub use admin::{AdminPeer, AdminPeerConfig};
+pub use discovery::DhtConfig;
+pub use events::NetworkEvent;
+pub use failover::{AdminEndpoint, FailoverConfig};
+#[cfg(feature = "fault-injection")]
+pub use faults::{FaultInjector, LinkFaults};
+pub use lanes::Emitter;
 pub use member::{MemberPeer, MemberPeerConfig};
+pub use metrics::PeerMetrics;
+pub use moderation::{AdminCommand, AdminControl, CommandReply};
 
-pub use peer_builder::{create_key, create_key_from_bytes, get_peer_id};
+pub use scoring::PeerScoreConfig;
+pub use shutdown::{ShutdownConfig, ShutdownSummary};
+pub use workspace::{AccessPolicy, WorkspaceConfig};
+
+pub use crate::peer::behaviour::MIN_REGISTRATION_TTL;
+
+pub use peer_builder::{create_key, create_key_from_bytes, get_peer_id, load_or_create_key};
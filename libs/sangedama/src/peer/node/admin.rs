@@ -1,32 +1,101 @@
 use std::collections::HashMap;
 use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use futures::StreamExt;
 use libp2p::multiaddr::Protocol;
-use libp2p::swarm::SwarmEvent;
+use libp2p::swarm::{
+    dial_opts::{DialOpts, PeerCondition},
+    SwarmEvent,
+};
 use libp2p::{
     gossipsub::{self, TopicHash},
-    identity, rendezvous, Multiaddr, PeerId, Swarm,
+    identify, identity, kad, rendezvous, request_response, Multiaddr, PeerId, Swarm,
 };
 use tokio::select;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamMap;
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn, Instrument};
 
 use crate::peer::behaviour::{PeerAdminBehaviour, PeerAdminEvent};
-use crate::peer::message::data::{EventType, NodeMessage};
+use crate::peer::message::clock::{unix_millis, HybridClock};
+use crate::peer::message::compression::{CompressionConfig, CompressionPeers};
+use crate::peer::message::data::{
+    EventType, NodeMessage, OutgoingMessage, Priority, DEFAULT_CLOCK_SKEW,
+};
+use crate::peer::message::direct::{DirectRequest, DirectResponse};
+use crate::peer::message::history::{HistoryConfig, HistoryResponse};
+use crate::peer::message::recording::{open_recorder, Direction, MessageRecorder};
+use crate::peer::message::validation::{
+    report_validation, MessageAcceptance, MessageValidator, PendingValidation, Route, Validated,
+    ValidationQueue,
+};
+use crate::peer::node::discovery::{workspace_key, DhtConfig};
+use crate::peer::node::events::{emit, NetworkEvent, NETWORK_EVENT_CAPACITY};
+use crate::peer::node::failover::{admin_topic, AdminHeartbeat, FailoverConfig};
+#[cfg(feature = "fault-injection")]
+use crate::peer::node::faults::FaultInjector;
+use crate::peer::node::faults::{FaultEvent, FaultLayer};
+use crate::peer::node::lanes::Emitter;
+use crate::peer::node::metrics::PeerMetrics;
+use crate::peer::node::moderation::{AdminCommand, AdminControl, BanList, KICK_GRACE};
+use crate::peer::node::scoring::{Graylist, PeerScoreConfig, SCORE_CHECK_INTERVAL};
+use crate::peer::node::shutdown::{close_connections, ShutdownConfig, ShutdownSummary};
+use crate::peer::node::workspace::{AccessPolicy, HostedWorkspace, WorkspaceConfig};
 use crate::peer::peer_swarm::create_swarm;
 
 #[derive(Default, Clone)]
 pub struct AdminPeerConfig {
     pub workspace_id: String,
     pub listen_port: Option<u16>,
+    /// Addresses to listen on, any of IPv4, IPv6, QUIC, TCP and WebSocket. When empty the
+    /// admin listens on QUIC over IPv4 at `listen_port`.
+    pub listen_addresses: Vec<Multiaddr>,
+    pub history: HistoryConfig,
+    /// Who may take part in the workspace, more can be hosted with [`AdminPeer::host_workspace`].
+    pub access: AccessPolicy,
+    /// TTL of the messages broadcast without one of their own.
+    pub message_ttl: Option<Duration>,
+    /// How far the clocks of senders may run behind ours before their messages look expired.
+    pub clock_skew: Duration,
+    /// Primary and standby admins of the workspace, `None` runs a single admin.
+    pub failover: Option<FailoverConfig>,
+    /// Advertise the workspace in the DHT so members can find this admin.
+    pub dht: Option<DhtConfig>,
+    /// Score peers and graylist the ones misbehaving on the workspace topic.
+    pub peer_score: Option<PeerScoreConfig>,
+    /// JSON file the bans are kept in, bans only last while the admin runs without it.
+    pub ban_list_path: Option<PathBuf>,
+    /// Compress large outgoing payloads while every peer on the topic can read them,
+    /// incoming ones are read either way.
+    pub compression: Option<CompressionConfig>,
+    /// JSON lines file every message sent and received is appended to, see
+    /// [`read_recording`](crate::peer::message::recording::read_recording).
+    pub recording: Option<PathBuf>,
+    pub shutdown: ShutdownConfig,
 }
 
 impl AdminPeerConfig {
     pub fn new(listen_port: u16, workspace_id: String) -> Self {
         Self {
             listen_port: Some(listen_port),
+            listen_addresses: vec![],
             workspace_id,
+            history: HistoryConfig::default(),
+            access: AccessPolicy::default(),
+            message_ttl: None,
+            clock_skew: DEFAULT_CLOCK_SKEW,
+            failover: None,
+            dht: None,
+            peer_score: None,
+            ban_list_path: None,
+            compression: None,
+            recording: None,
+            shutdown: ShutdownConfig::default(),
         }
     }
 
@@ -36,6 +105,14 @@ impl AdminPeerConfig {
             .with(Protocol::Udp(self.listen_port.unwrap_or(0)))
             .with(Protocol::QuicV1)
     }
+
+    pub fn get_listen_addresses(&self) -> Vec<Multiaddr> {
+        if self.listen_addresses.is_empty() {
+            vec![self.get_listen_address()]
+        } else {
+            self.listen_addresses.clone()
+        }
+    }
 }
 
 pub struct AdminPeer {
@@ -44,67 +121,526 @@ pub struct AdminPeer {
     pub config: AdminPeerConfig,
 
     connected_peers: HashMap<TopicHash, Vec<PeerId>>,
+    workspaces: HashMap<TopicHash, HostedWorkspace>,
+
+    is_primary: bool,
+    /// When the last primary heartbeat arrived, `None` until a standby has seen one.
+    last_heartbeat: Option<Instant>,
+    started_at: Instant,
+
+    clock: HybridClock,
+    graylist: Graylist,
+    compression_peers: CompressionPeers,
+    validation: ValidationQueue,
+    bans: BanList,
+    pending_kicks: Vec<(PeerId, Instant)>,
 
-    outside_tx: tokio::sync::mpsc::Sender<NodeMessage>,
+    control_tx: tokio::sync::mpsc::Sender<AdminCommand>,
+    control_rx: tokio::sync::mpsc::Receiver<AdminCommand>,
 
-    inside_rx: tokio::sync::mpsc::Receiver<Vec<u8>>,
-    inside_tx: tokio::sync::mpsc::Sender<Vec<u8>>,
+    network_tx: tokio::sync::broadcast::Sender<NetworkEvent>,
+    listen_addresses_tx: tokio::sync::watch::Sender<Vec<Multiaddr>>,
+
+    inside_rx: StreamMap<TopicHash, ReceiverStream<OutgoingMessage>>,
+    inside_control_rx: StreamMap<TopicHash, ReceiverStream<OutgoingMessage>>,
+    metrics: Arc<PeerMetrics>,
+    faults: FaultLayer,
+    recorder: Option<MessageRecorder>,
 }
 
 impl AdminPeer {
     pub async fn create(
         config: AdminPeerConfig,
         key: identity::Keypair,
-    ) -> (Self, tokio::sync::mpsc::Receiver<NodeMessage>) {
-        let swarm = create_swarm::<PeerAdminBehaviour>(key.clone()).await;
-        let (outside_tx, outside_rx) = tokio::sync::mpsc::channel::<NodeMessage>(100);
-
-        let (inside_tx, inside_rx) = tokio::sync::mpsc::channel::<Vec<u8>>(100);
+    ) -> Result<(Self, tokio::sync::mpsc::Receiver<NodeMessage>), String> {
+        let mut swarm = create_swarm::<PeerAdminBehaviour>(key.clone()).await;
+        if let Some(peer_score) = &config.peer_score {
+            peer_score.apply(&mut swarm.behaviour_mut().gossip_sub, &config.workspace_id)?;
+        }
+        let recorder = open_recorder(config.recording.as_ref());
+        let (mut workspace, outside_rx, inside_rx, inside_control_rx) =
+            HostedWorkspace::new(WorkspaceConfig {
+                workspace_id: config.workspace_id.clone(),
+                history: config.history.clone(),
+                access: config.access.clone(),
+                message_ttl: config.message_ttl,
+            });
+        if let Some(recorder) = &recorder {
+            workspace.outside_tx =
+                recorder.tap(config.workspace_id.clone(), workspace.outside_tx.clone());
+        }
+        let topic = workspace.topic.hash();
+        let mut inside_streams = StreamMap::new();
+        inside_streams.insert(topic.clone(), ReceiverStream::new(inside_rx));
+        let mut inside_control_streams = StreamMap::new();
+        inside_control_streams.insert(topic.clone(), ReceiverStream::new(inside_control_rx));
+        let bans = BanList::load(config.ban_list_path.clone());
+        for peer_id in bans.peers() {
+            swarm.behaviour_mut().blocked_peers.block_peer(peer_id);
+        }
+        let (control_tx, control_rx) = tokio::sync::mpsc::channel::<AdminCommand>(100);
+        let is_primary = config
+            .failover
+            .as_ref()
+            .and_then(|f| f.rank_of(swarm.local_peer_id()))
+            .is_none_or(|rank| rank == 0);
 
-        (
+        Ok((
             Self {
                 config,
                 id: swarm.local_peer_id().to_string(),
                 swarm,
                 connected_peers: HashMap::new(),
-                outside_tx,
+                workspaces: HashMap::from([(topic, workspace)]),
+                is_primary,
+                last_heartbeat: None,
+                started_at: Instant::now(),
+                clock: HybridClock::new(),
+                graylist: Graylist::default(),
+                compression_peers: CompressionPeers::default(),
+                validation: ValidationQueue::new(),
+                bans,
+                pending_kicks: vec![],
+                control_tx,
+                control_rx,
+                network_tx: tokio::sync::broadcast::channel(NETWORK_EVENT_CAPACITY).0,
+                listen_addresses_tx: tokio::sync::watch::channel(vec![]).0,
 
-                inside_tx,
-                inside_rx,
+                inside_rx: inside_streams,
+                inside_control_rx: inside_control_streams,
+                metrics: Arc::new(PeerMetrics::default()),
+                faults: FaultLayer::new(),
+                recorder,
             },
             outside_rx,
+        ))
+    }
+
+    pub fn emitter(&self) -> Emitter {
+        self.workspaces[&self.primary_topic()].emitter()
+    }
+
+    /// Broadcasts to a workspace hosted by this admin, `None` if it is not hosted.
+    pub fn emitter_for(&self, workspace_id: &str) -> Option<Emitter> {
+        self.workspace(workspace_id).map(|w| w.emitter())
+    }
+
+    /// Hosts one more workspace on the same port and identity, with its own topic, roster,
+    /// history and access policy. Its traffic is delivered on the returned receiver.
+    pub fn host_workspace(
+        &mut self,
+        config: WorkspaceConfig,
+    ) -> Result<tokio::sync::mpsc::Receiver<NodeMessage>, String> {
+        if self.workspace(&config.workspace_id).is_some() {
+            return Err(format!(
+                "Workspace {} is already hosted",
+                config.workspace_id
+            ));
+        }
+        if let Some(peer_score) = &self.config.peer_score {
+            peer_score.apply_to_workspace(
+                &mut self.swarm.behaviour_mut().gossip_sub,
+                &config.workspace_id,
+            )?;
+        }
+        let (mut workspace, outside_rx, inside_rx, inside_control_rx) =
+            HostedWorkspace::new(config);
+        if let Some(recorder) = &self.recorder {
+            workspace.outside_tx =
+                recorder.tap(workspace.workspace_id.clone(), workspace.outside_tx.clone());
+        }
+        info!(
+            "Admin {} hosting workspace {}",
+            self.id, workspace.workspace_id
+        );
+        let topic = workspace.topic.hash();
+        self.inside_rx
+            .insert(topic.clone(), ReceiverStream::new(inside_rx));
+        self.inside_control_rx
+            .insert(topic.clone(), ReceiverStream::new(inside_control_rx));
+        self.workspaces.insert(topic, workspace);
+        Ok(outside_rx)
+    }
+
+    fn workspace(&self, workspace_id: &str) -> Option<&HostedWorkspace> {
+        self.workspaces
+            .get(&gossipsub::IdentTopic::new(workspace_id).hash())
+    }
+
+    fn primary_topic(&self) -> TopicHash {
+        gossipsub::IdentTopic::new(self.config.workspace_id.clone()).hash()
+    }
+
+    // The hosted workspace a topic belongs to, either its data or its control lane.
+    fn workspace_carrying(&self, topic: &TopicHash) -> Option<TopicHash> {
+        self.workspaces
+            .iter()
+            .find(|(_, w)| w.carries(topic))
+            .map(|(key, _)| key.clone())
+    }
+
+    // The workspaces a peer takes part in, notices about it go to those alone.
+    fn workspaces_of(&self, peer_id: &PeerId) -> Vec<TopicHash> {
+        self.connected_peers
+            .iter()
+            .filter(|(topic, peers)| self.workspaces.contains_key(topic) && peers.contains(peer_id))
+            .map(|(topic, _)| topic.clone())
+            .collect()
+    }
+
+    /// Network and lifecycle events, only those sent after subscribing are received.
+    pub fn network_events(&self) -> tokio::sync::broadcast::Receiver<NetworkEvent> {
+        self.network_tx.subscribe()
+    }
+
+    /// The addresses the admin is bound to once running, with the actual ports when 0 was asked.
+    pub fn listen_addresses(&self) -> tokio::sync::watch::Receiver<Vec<Multiaddr>> {
+        self.listen_addresses_tx.subscribe()
+    }
+
+    pub fn metrics(&self) -> Arc<PeerMetrics> {
+        self.metrics.clone()
+    }
+
+    /// Controls the faults injected into the gossipsub messages this peer receives, see
+    /// the `faults` module for what is left alone.
+    #[cfg(feature = "fault-injection")]
+    pub fn fault_injector(&self) -> FaultInjector {
+        self.faults.injector()
+    }
+
+    pub fn control(&self) -> AdminControl {
+        AdminControl::new(self.control_tx.clone())
+    }
+
+    /// Messages received from now on are only delivered and forwarded if the validator accepts them.
+    pub fn set_validator(&mut self, validator: Arc<dyn MessageValidator>) {
+        let topic = self.primary_topic();
+        if let Some(workspace) = self.workspaces.get_mut(&topic) {
+            workspace.validator = Some(validator);
+        }
+    }
+
+    /// Like [`AdminPeer::set_validator`] for a workspace added with [`AdminPeer::host_workspace`].
+    pub fn set_workspace_validator(
+        &mut self,
+        workspace_id: &str,
+        validator: Arc<dyn MessageValidator>,
+    ) -> Result<(), String> {
+        let topic = gossipsub::IdentTopic::new(workspace_id).hash();
+        let workspace = self
+            .workspaces
+            .get_mut(&topic)
+            .ok_or_else(|| format!("Workspace {} is not hosted", workspace_id))?;
+        workspace.validator = Some(validator);
+        Ok(())
+    }
+
+    async fn on_command(&mut self, command: AdminCommand) {
+        match command {
+            AdminCommand::Kick { peer_id, reply } => {
+                let topics = self.workspaces_of(&peer_id);
+                let is_member = self
+                    .connected_peers
+                    .values()
+                    .any(|peers| peers.contains(&peer_id));
+                if !is_member && !self.swarm.is_connected(&peer_id) {
+                    let _ = reply.send(Err(format!("Peer {} is not a member", peer_id)));
+                    return;
+                }
+                info!("Kicking {}", peer_id);
+                self.remove_peer(peer_id);
+                self.notify(
+                    topics,
+                    EventType::PeerKicked {
+                        peer_id: peer_id.to_string(),
+                    },
+                )
+                .await;
+                let _ = reply.send(Ok(()));
+            }
+            AdminCommand::Ban {
+                peer_id,
+                duration,
+                reply,
+            } => {
+                let until = duration.map(|d| unix_millis() + d.as_millis() as u64);
+                info!("Banning {} until {:?}", peer_id, until);
+                let saved = self.bans.insert(peer_id, until);
+                let topics = self.workspaces_of(&peer_id);
+                self.remove_peer(peer_id);
+                self.notify(
+                    topics,
+                    EventType::PeerBanned {
+                        peer_id: peer_id.to_string(),
+                        until,
+                    },
+                )
+                .await;
+                let _ = reply.send(saved);
+            }
+            AdminCommand::Unban { peer_id, reply } => {
+                if self.bans.remove(&peer_id) {
+                    self.unban(peer_id).await;
+                    let _ = reply.send(Ok(()));
+                } else {
+                    let _ = reply.send(Err(format!("Peer {} is not banned", peer_id)));
+                }
+            }
+        }
+    }
+
+    // The peer is only cut off after a grace period, so it still gets the event removing it.
+    fn remove_peer(&mut self, peer_id: PeerId) {
+        for peers in self.connected_peers.values_mut() {
+            peers.retain(|p| p != &peer_id);
+        }
+        self.pending_kicks
+            .push((peer_id, Instant::now() + KICK_GRACE));
+    }
+
+    async fn unban(&mut self, peer_id: PeerId) {
+        info!("Unbanning {}", peer_id);
+        self.swarm
+            .behaviour_mut()
+            .blocked_peers
+            .unblock_peer(peer_id);
+        self.notify(
+            vec![self.primary_topic()],
+            EventType::PeerUnbanned {
+                peer_id: peer_id.to_string(),
+            },
         )
+        .await;
     }
 
-    pub fn emitter(&self) -> tokio::sync::mpsc::Sender<Vec<u8>> {
-        self.inside_tx.clone()
+    async fn check_moderation(&mut self) {
+        let now = Instant::now();
+        let (due, pending) = self.pending_kicks.drain(..).partition(|(_, at)| *at <= now);
+        self.pending_kicks = pending;
+        for (peer_id, _) in due {
+            if self.bans.contains(&peer_id) {
+                self.swarm.behaviour_mut().blocked_peers.block_peer(peer_id);
+            } else if self.swarm.disconnect_peer_id(peer_id).is_err() {
+                debug!("Kicked peer {} was not connected", peer_id);
+            }
+        }
+        for peer_id in self.bans.expire(unix_millis()) {
+            self.unban(peer_id).await;
+        }
     }
 
-    pub async fn run(&mut self, address: Option<Multiaddr>, cancellation_token: CancellationToken) {
-        let address_ = if address.is_none() {
-            Multiaddr::empty()
-                .with(Protocol::Ip4(Ipv4Addr::UNSPECIFIED))
-                .with(Protocol::Udp(self.config.listen_port.unwrap_or(0)))
-                .with(Protocol::QuicV1)
-        } else {
-            address.unwrap()
+    // Tells the members and the owner of each of the workspaces.
+    async fn notify(&mut self, topics: Vec<TopicHash>, event: EventType) {
+        for topic in topics {
+            let message = NodeMessage::new_event(&mut self.clock, self.id.clone(), event.clone());
+            if self.is_primary {
+                if let Err(e) = self
+                    .swarm
+                    .behaviour_mut()
+                    .gossip_sub
+                    .publish(topic.clone(), message.to_bytes())
+                {
+                    error!("Failed to publish {:?}: {:?}", message, e);
+                } else {
+                    self.record_sent(&topic, &message);
+                }
+            }
+            self.send_outside(&topic, message).await;
+        }
+    }
+
+    fn record_sent(&self, topic: &TopicHash, message: &NodeMessage) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record(Direction::Sent, topic.as_str(), message);
+        }
+    }
+
+    async fn send_outside(&mut self, topic: &TopicHash, message: NodeMessage) {
+        let Some(outside_tx) = self.workspaces.get(topic).map(|w| w.outside_tx.clone()) else {
+            return;
         };
+        if let Err(e) = outside_tx.send(message).await {
+            error!("Failed to send message to outside: {:?}", e);
+        }
+    }
 
-        self.swarm.listen_on(address_.clone()).unwrap();
-        info!("Listening on: {:?}", address_.to_string());
+    async fn check_peer_scores(&mut self) {
+        let Some(peer_score) = &self.config.peer_score else {
+            return;
+        };
+        let graylisted = self
+            .graylist
+            .check(&self.swarm.behaviour().gossip_sub, peer_score);
+        for (peer_id, score) in graylisted {
+            for topic in self.workspaces_of(&peer_id) {
+                let event = NodeMessage::new_event(
+                    &mut self.clock,
+                    self.id.clone(),
+                    EventType::PeerGraylisted {
+                        peer_id: peer_id.to_string(),
+                        score,
+                    },
+                );
+                self.send_outside(&topic, event).await;
+            }
+        }
+    }
+
+    async fn on_validated(&mut self, validated: Validated) {
+        let Validated {
+            route,
+            message,
+            acceptance,
+            node_message,
+        } = validated;
+        route.report(&mut self.swarm.behaviour_mut().gossip_sub, acceptance);
+        let (Some(msg), Some(topic)) = (node_message, self.workspace_carrying(&message.topic))
+        else {
+            return;
+        };
+        self.clock.update(msg.hlc());
+        // Only the signed author can announce its own departure, whatever the payload says.
+        if let NodeMessage::Event {
+            event: EventType::AgentLeft { .. },
+            ..
+        } = &msg
+        {
+            if let (Some(author), Some(peers)) =
+                (message.source, self.connected_peers.get_mut(&topic))
+            {
+                peers.retain(|p| *p != author);
+            }
+        }
+        let span = msg.handling_span();
+        let Some(workspace) = self.workspaces.get_mut(&topic) else {
+            return;
+        };
+        // Direct messages are for this admin alone, they are not replayed to anyone.
+        if message.topic == topic && matches!(route, Route::Gossip { .. }) {
+            workspace.history.push(topic.to_string(), msg.clone());
+        }
+        if let Err(e) = workspace.outside_tx.send(msg).instrument(span).await {
+            error!("Failed to send message to outside: {:?}", e);
+        }
+    }
+
+    /// Listens on `address`, or on the configured addresses when it is `None`, until cancelled.
+    /// Fails right away when it cannot listen on any of them.
+    pub async fn run(
+        &mut self,
+        address: Option<Multiaddr>,
+        cancellation_token: CancellationToken,
+    ) -> Result<ShutdownSummary, String> {
+        let addresses = match address {
+            Some(address) => vec![address],
+            None => self.config.get_listen_addresses(),
+        };
+        let mut failures = vec![];
+        for address in &addresses {
+            match self.swarm.listen_on(address.clone()) {
+                Ok(_) => info!("Listening on: {:?}", address.to_string()),
+                Err(e) => {
+                    error!("Failed to listen on {}: {:?}", address, e);
+                    failures.push(format!("{}: {:?}", address, e));
+                }
+            }
+        }
+        if failures.len() == addresses.len() {
+            return Err(format!(
+                "Admin {} could not listen on any address [{}]",
+                self.id,
+                failures.join(", ")
+            ));
+        }
+
+        let heartbeat_interval = self
+            .config
+            .failover
+            .as_ref()
+            .map(|f| f.heartbeat_interval)
+            .unwrap_or(std::time::Duration::from_secs(1));
+        let mut heartbeat = tokio::time::interval(heartbeat_interval);
+        let mut score_check = tokio::time::interval(SCORE_CHECK_INTERVAL);
+        let mut moderation_check = tokio::time::interval(KICK_GRACE);
+        self.started_at = Instant::now();
+        self.validation
+            .start(self.metrics.clone(), self.config.clock_skew);
+        self.join_admins();
+        self.dial_admins();
+        self.join_dht();
+        if self.is_primary {
+            self.advertise(true);
+        }
 
         loop {
             select! {
                 _ = cancellation_token.cancelled() => {
                     break;
                 }
+                _ = heartbeat.tick() => {
+                    self.on_heartbeat_tick().await;
+                }
+                _ = score_check.tick() => {
+                    self.check_peer_scores().await;
+                }
+                _ = moderation_check.tick() => {
+                    self.check_moderation().await;
+                }
+                validated = self.validation.next() => {
+                    self.on_validated(validated).await;
+                }
+                command = self.control_rx.recv() => {
+                    if let Some(command) = command {
+                        self.on_command(command).await;
+                    }
+                }
+                fault = self.faults.next() => match fault {
+                    FaultEvent::Delayed(event) => self.process_event((*event).into()).await,
+                    FaultEvent::Partitioned(peers) => {
+                        for peer_id in peers {
+                            let _ = self.swarm.disconnect_peer_id(peer_id);
+                        }
+                    }
+                },
                 event = self.swarm.select_next_some() => {
+                    if let Some(network_event) = NetworkEvent::from_swarm(&event) {
+                        emit(&self.network_tx, network_event);
+                    }
                     match event {
+                        SwarmEvent::ConnectionEstablished { peer_id, .. } if self.faults.is_partitioned(&peer_id) => {
+                            warn!("Cutting connection to partitioned peer {}", peer_id);
+                            let _ = self.swarm.disconnect_peer_id(peer_id);
+                        }
                        SwarmEvent::ConnectionEstablished { peer_id, .. } => {
                             info!("Connected to {}", peer_id);
                         }
-                        SwarmEvent::ConnectionClosed { peer_id, .. } => {
+                        SwarmEvent::ConnectionClosed { peer_id, num_established, .. } => {
                             info!("Disconnected from {}", peer_id);
+                            if num_established == 0 {
+                                self.graylist.forget(&peer_id);
+                                self.compression_peers.forget(&peer_id);
+                            }
+                        }
+                        SwarmEvent::NewListenAddr { address, .. } => {
+                            info!("Listening on {}", address);
+                            self.listen_addresses_tx.send_modify(|addresses| {
+                                addresses.push(address.clone());
+                            });
+                            if self.config.dht.is_some() {
+                                self.swarm.add_external_address(address);
+                            }
+                        }
+                        SwarmEvent::ExpiredListenAddr { address, .. } => {
+                            info!("No longer listening on {}", address);
+                            self.listen_addresses_tx.send_modify(|addresses| {
+                                addresses.retain(|a| a != &address);
+                            });
+                        }
+                        SwarmEvent::Behaviour(PeerAdminEvent::GossipSub(event)) => {
+                            if let Some(event) = self.faults.inbound(event) {
+                                self.process_event(event.into()).await;
+                            }
                         }
                         SwarmEvent::Behaviour(event) => {
                             self.process_event(event).await;
@@ -115,75 +651,572 @@ impl AdminPeer {
                     }
                 }
 
-                message = self.inside_rx.recv() => {
-                    if let Some(message) = message {
-                        let topic = gossipsub::IdentTopic::new(self.config.workspace_id.clone());
-
-                        let distributed_message = NodeMessage::Message {
-                            data: message,
-                            time: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs_f64() as u64,
-                            created_by: self.id.clone(),
-                        };
-                        match self.swarm.behaviour_mut().gossip_sub.publish(topic,distributed_message.to_bytes()){
-                            Ok(_) => {}
-                            Err(e) => {
-                                error!("Failed to broadcast message: {:?}", e);
-                            }
-                        }
+                message = self.inside_control_rx.next() => {
+                    if let Some((topic, message)) = message {
+                        self.broadcast(&topic, message);
+                    }
+                }
+                message = self.inside_rx.next() => {
+                    if let Some((topic, message)) = message {
+                        self.flush_control();
+                        self.broadcast(&topic, message);
                     }
                 }
             }
         }
+        Ok(self.shutdown().await)
+    }
+
+    // Control messages queued meanwhile overtake the data message about to go out.
+    fn flush_control(&mut self) -> (usize, usize) {
+        let mut queued = vec![];
+        for (topic, messages) in self.inside_control_rx.iter_mut() {
+            while let Ok(message) = messages.as_mut().try_recv() {
+                queued.push((topic.clone(), message));
+            }
+        }
+        let (mut flushed, mut dropped) = (0, 0);
+        for (topic, message) in queued {
+            if self.broadcast(&topic, message) {
+                flushed += 1;
+            } else {
+                dropped += 1;
+            }
+        }
+        (flushed, dropped)
+    }
+
+    fn broadcast(&mut self, topic: &TopicHash, message: OutgoingMessage) -> bool {
+        if !self.is_primary {
+            warn!("Standby admin {} dropped outgoing message", self.id);
+            return false;
+        }
+        let workspace_topic = topic.clone();
+        let Some((topic, message_ttl)) = self.workspaces.get(topic).map(|w| {
+            let topic = match message.priority {
+                Priority::Control => w.control_topic.clone(),
+                Priority::Data => w.topic.clone(),
+            };
+            (topic, w.message_ttl)
+        }) else {
+            return false;
+        };
+
+        let span = message.broadcast_span(&self.id, &topic.to_string());
+        span.in_scope(|| {
+            let distributed_message =
+                NodeMessage::new_message(&mut self.clock, self.id.clone(), message.data)
+                    .with_ttl(message.ttl.or(message_ttl));
+            if let Some(to) = message.to {
+                return self.send_direct(&workspace_topic, to, distributed_message);
+            }
+            let compression = self.compression_peers.config_for(
+                self.config.compression.as_ref(),
+                &self.swarm.behaviour().gossip_sub,
+                &topic.hash(),
+            );
+            let published = match self.swarm.behaviour_mut().gossip_sub.publish(
+                topic.clone(),
+                distributed_message.to_compressed_bytes(compression),
+            ) {
+                Ok(_) => {
+                    self.record_sent(&topic.hash(), &distributed_message);
+                    true
+                }
+                Err(e) => {
+                    error!("Failed to broadcast message: {:?}", e);
+                    false
+                }
+            };
+            // Kept even when nobody is listening yet, late joiners get it on replay. Control
+            // traffic is only meaningful live and never replayed.
+            if let Some(workspace) = self.workspaces.get_mut(&topic.hash()) {
+                workspace
+                    .history
+                    .push(topic.to_string(), distributed_message);
+            }
+            published
+        })
+    }
+
+    // Sends a message to a member of the workspace alone, it is kept out of the history.
+    fn send_direct(&mut self, topic: &TopicHash, to: PeerId, message: NodeMessage) -> bool {
+        if !self
+            .connected_peers
+            .get(topic)
+            .is_some_and(|peers| peers.contains(&to))
+        {
+            error!("Direct: {:?} is not a member of {}", to, topic);
+            return false;
+        }
+        let request = DirectRequest {
+            topic: topic.to_string(),
+            from: self.id.clone(),
+            to: to.to_string(),
+            data: message.to_bytes(),
+        };
+        self.swarm.behaviour_mut().direct.send_request(&to, request);
+        self.record_sent(topic, &message);
+        true
+    }
+
+    // Takes a message a peer addressed to this admin, or to a member of the workspace that the
+    // admin relays it to unseen. Whether it was taken is all the sender learns.
+    fn on_direct(&mut self, peer: PeerId, request: DirectRequest) -> bool {
+        let Some(topic) = self.workspace_carrying(&TopicHash::from_raw(request.topic.clone()))
+        else {
+            return false;
+        };
+        let workspace = &self.workspaces[&topic];
+        if self.bans.contains(&peer)
+            || (!workspace.access.allows(&peer) && !self.is_admin_peer(&peer))
+            || request.from != peer.to_string()
+        {
+            warn!("Direct: refusing message from {:?}", peer);
+            return false;
+        }
+        if request.to == self.id {
+            let pending = PendingValidation {
+                route: Route::Direct,
+                message: gossipsub::Message {
+                    source: Some(peer),
+                    data: request.data,
+                    sequence_number: None,
+                    topic,
+                },
+                validator: workspace.validator.clone(),
+            };
+            return self.validation.submit(pending).is_ok();
+        }
+        let to = PeerId::from_str(&request.to).ok().filter(|to| {
+            self.connected_peers
+                .get(&topic)
+                .is_some_and(|peers| peers.contains(to))
+        });
+        let Some(to) = to else {
+            warn!("Direct: {} is not a member of {}", request.to, topic);
+            return false;
+        };
+        self.swarm.behaviour_mut().direct.send_request(&to, request);
+        true
+    }
+
+    async fn shutdown(&mut self) -> ShutdownSummary {
+        info!("Admin {} shutting down", self.id);
+        let deadline = tokio::time::Instant::now() + self.config.shutdown.drain_deadline;
+        let mut summary = ShutdownSummary::default();
+
+        (summary.flushed_messages, summary.dropped_messages) = self.flush_control();
+        let mut queued = vec![];
+        for (topic, messages) in self.inside_rx.iter_mut() {
+            while let Ok(message) = messages.as_mut().try_recv() {
+                queued.push((topic.clone(), message));
+            }
+        }
+        for (topic, message) in queued {
+            if self.broadcast(&topic, message) {
+                summary.flushed_messages += 1;
+            } else {
+                summary.dropped_messages += 1;
+            }
+        }
+        if self.is_primary {
+            let topics = self.workspaces.keys().cloned().collect();
+            self.notify(
+                topics,
+                EventType::AgentLeft {
+                    peer_id: self.id.clone(),
+                },
+            )
+            .await;
+        }
+        let mut topics: Vec<_> = self
+            .workspaces
+            .values()
+            .flat_map(|w| [w.topic.clone(), w.control_topic.clone()])
+            .collect();
+        topics.push(gossipsub::IdentTopic::new(admin_topic(
+            &self.config.workspace_id,
+        )));
+        for topic in topics {
+            if let Err(e) = self.swarm.behaviour_mut().gossip_sub.unsubscribe(&topic) {
+                error!("Failed to unsubscribe from {}: {:?}", topic, e);
+            }
+        }
+
+        close_connections(&mut self.swarm, deadline, &mut summary, &self.network_tx).await;
+        info!("Admin {} stopped: {:?}", self.id, summary);
+        summary
+    }
+
+    fn rank(&self) -> Option<usize> {
+        self.config
+            .failover
+            .as_ref()
+            .and_then(|f| f.rank_of(self.swarm.local_peer_id()))
+    }
+
+    fn is_admin_peer(&self, peer_id: &PeerId) -> bool {
+        self.config
+            .failover
+            .as_ref()
+            .is_some_and(|f| f.is_admin(peer_id))
+    }
+
+    // Standbys follow the workspace topic to replicate its history and
+    // listen on the admin topic to receive heartbeats.
+    fn join_admins(&mut self) {
+        let Some(rank) = self.rank() else {
+            return;
+        };
+        let mut topics = vec![gossipsub::IdentTopic::new(admin_topic(
+            &self.config.workspace_id,
+        ))];
+        if rank > 0 {
+            for workspace in self.workspaces.values() {
+                topics.push(workspace.topic.clone());
+                topics.push(workspace.control_topic.clone());
+            }
+        }
+        for topic in topics {
+            if let Err(e) = self.swarm.behaviour_mut().gossip_sub.subscribe(&topic) {
+                error!("Failed to subscribe to {}: {:?}", topic, e);
+            }
+        }
+    }
+
+    // Connects to every admin ranked above this one that is not connected yet.
+    fn dial_admins(&mut self) {
+        let (Some(failover), Some(rank)) = (self.config.failover.clone(), self.rank()) else {
+            return;
+        };
+        for admin in failover.admins.iter().take(rank) {
+            if self.swarm.is_connected(&admin.peer_id) {
+                continue;
+            }
+            let dial_opts = DialOpts::peer_id(admin.peer_id)
+                .addresses(vec![admin.address.clone()])
+                .condition(PeerCondition::Disconnected)
+                .build();
+            if let Err(e) = self.swarm.dial(dial_opts) {
+                error!("Failed to dial admin {}: {:?}", admin.peer_id, e);
+            }
+        }
+    }
+
+    fn join_dht(&mut self) {
+        let Some(dht) = self.config.dht.clone() else {
+            return;
+        };
+        let kad = &mut self.swarm.behaviour_mut().kad;
+        kad.set_mode(Some(kad::Mode::Server));
+        for node in dht.bootstrap {
+            kad.add_address(&node.peer_id, node.address);
+        }
+        if let Err(e) = kad.bootstrap() {
+            debug!("DHT bootstrap skipped: {:?}", e);
+        }
+    }
+
+    // Only the primary is listed as provider, so members never attach to a standby.
+    fn advertise(&mut self, providing: bool) {
+        if self.config.dht.is_none() {
+            return;
+        }
+        let keys: Vec<_> = self
+            .workspaces
+            .values()
+            .map(|w| (w.workspace_id.clone(), workspace_key(&w.workspace_id)))
+            .collect();
+        let kad = &mut self.swarm.behaviour_mut().kad;
+        for (workspace_id, key) in keys {
+            if !providing {
+                kad.stop_providing(&key);
+            } else if let Err(e) = kad.start_providing(key) {
+                error!(
+                    "Failed to advertise workspace {} in the DHT: {:?}",
+                    workspace_id, e
+                );
+            }
+        }
+    }
+
+    async fn on_heartbeat_tick(&mut self) {
+        let (Some(failover), Some(rank)) = (self.config.failover.clone(), self.rank()) else {
+            return;
+        };
+        if self.is_primary {
+            let heartbeat = AdminHeartbeat {
+                admin: self.id.clone(),
+                rank,
+                time: std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_millis() as u64,
+                roster: self
+                    .connected_peers
+                    .iter()
+                    .map(|(topic, peers)| {
+                        (
+                            topic.to_string(),
+                            peers.iter().map(|p| p.to_string()).collect(),
+                        )
+                    })
+                    .collect(),
+            };
+            let topic = gossipsub::IdentTopic::new(admin_topic(&self.config.workspace_id));
+            if let Err(e) = self
+                .swarm
+                .behaviour_mut()
+                .gossip_sub
+                .publish(topic, heartbeat.to_bytes())
+            {
+                debug!("Failed to publish admin heartbeat: {:?}", e);
+            }
+        } else if self.primary_lost(&failover, rank) {
+            info!("Primary admin lost, {} taking over the workspace", self.id);
+            self.is_primary = true;
+            self.advertise(true);
+            self.emit_admin_changed(self.id.clone()).await;
+        } else {
+            self.dial_admins();
+        }
+    }
+
+    // Counts from the last heartbeat, or from startup plus a grace period while
+    // no primary has been heard from yet.
+    fn primary_lost(&self, failover: &FailoverConfig, rank: usize) -> bool {
+        let timeout = failover.failover_timeout * rank as u32;
+        match self.last_heartbeat {
+            Some(last) => last.elapsed() >= timeout,
+            None => self.started_at.elapsed() >= failover.startup_grace + timeout,
+        }
+    }
+
+    async fn on_admin_heartbeat(&mut self, heartbeat: AdminHeartbeat) {
+        if heartbeat.admin == self.id {
+            return;
+        }
+        if self.is_primary {
+            if self.rank().is_some_and(|rank| heartbeat.rank < rank) {
+                info!(
+                    "Admin {} has precedence, {} stepping down",
+                    heartbeat.admin, self.id
+                );
+                self.is_primary = false;
+                self.advertise(false);
+                self.last_heartbeat = Some(Instant::now());
+                self.emit_admin_changed(heartbeat.admin).await;
+            }
+            return;
+        }
+        self.last_heartbeat = Some(Instant::now());
+        // Peers connected to this standby itself stay listed, the rest follows the primary.
+        let mut roster: HashMap<TopicHash, Vec<PeerId>> = heartbeat
+            .roster
+            .into_iter()
+            .map(|(topic, peers)| {
+                (
+                    TopicHash::from_raw(topic),
+                    peers
+                        .iter()
+                        .filter_map(|p| PeerId::from_str(p).ok())
+                        .collect(),
+                )
+            })
+            .collect();
+        for (topic, peers) in &self.connected_peers {
+            let merged = roster.entry(topic.clone()).or_default();
+            for peer in peers {
+                if self.swarm.is_connected(peer) && !merged.contains(peer) {
+                    merged.push(*peer);
+                }
+            }
+        }
+        self.connected_peers = roster;
+    }
+
+    async fn emit_admin_changed(&mut self, peer_id: String) {
+        let topics: Vec<_> = self.workspaces.keys().cloned().collect();
+        for topic in topics {
+            let event = NodeMessage::new_event(
+                &mut self.clock,
+                self.id.clone(),
+                EventType::AdminChanged {
+                    peer_id: peer_id.clone(),
+                },
+            );
+            self.send_outside(&topic, event).await;
+        }
     }
 
     async fn process_event(&mut self, event: PeerAdminEvent) {
+        let network_event = match &event {
+            PeerAdminEvent::Rendezvous(event) => NetworkEvent::from_rendezvous_server(event),
+            PeerAdminEvent::GossipSub(event) => NetworkEvent::from_gossipsub(event),
+            _ => None,
+        };
+        if let Some(network_event) = network_event {
+            emit(&self.network_tx, network_event);
+        }
         match event {
             PeerAdminEvent::Rendezvous(event) => match event {
-                rendezvous::server::Event::PeerRegistered { peer, .. } => {
+                rendezvous::server::Event::PeerRegistered { peer, registration } => {
                     info!("RendezvousServerConnected: {:?}", peer);
 
-                    let topic = gossipsub::IdentTopic::new(self.config.workspace_id.clone());
-                    self.swarm
-                        .behaviour_mut()
-                        .gossip_sub
-                        .subscribe(&topic)
-                        .unwrap();
+                    let topic = self
+                        .workspaces
+                        .values()
+                        .find(|w| w.namespace == registration.namespace)
+                        .map(|w| (w.topic.clone(), w.control_topic.clone()));
+                    match topic {
+                        Some((topic, control_topic)) => {
+                            let gossip_sub = &mut self.swarm.behaviour_mut().gossip_sub;
+                            gossip_sub.subscribe(&topic).unwrap();
+                            gossip_sub.subscribe(&control_topic).unwrap();
+                        }
+                        None => {
+                            warn!(
+                                "{:?} registered for unknown workspace {}",
+                                peer, registration.namespace
+                            );
+                        }
+                    }
                 }
                 _ => {
                     info!("RendezvousServer: {:?}", event);
                 }
             },
-            PeerAdminEvent::Ping(_) => {
-                // info!( "Ping: {:?}", event);
+            PeerAdminEvent::Ping(event) => {
+                debug!("Ping: {:?}", event);
+            }
+            PeerAdminEvent::Identify(event) => {
+                if let identify::Event::Received { peer_id, info } = &event {
+                    self.compression_peers
+                        .identified(*peer_id, &info.agent_version);
+                    if self.config.dht.is_some() {
+                        for address in info.listen_addrs.iter() {
+                            self.swarm
+                                .behaviour_mut()
+                                .kad
+                                .add_address(peer_id, address.clone());
+                        }
+                    }
+                }
+                debug!("Identify: {:?}", event);
             }
-            PeerAdminEvent::Identify(_) => {
-                // info!( "Identify: {:?}", event);
+            PeerAdminEvent::Kad(event) => {
+                debug!("Kad: {:?}", event);
             }
+            PeerAdminEvent::Direct(event) => match event {
+                request_response::Event::Message {
+                    peer,
+                    message:
+                        request_response::Message::Request {
+                            request, channel, ..
+                        },
+                } => {
+                    let accepted = self.on_direct(peer, request);
+                    if self
+                        .swarm
+                        .behaviour_mut()
+                        .direct
+                        .send_response(channel, DirectResponse { accepted })
+                        .is_err()
+                    {
+                        error!("Direct: failed to reply to {:?}", peer);
+                    }
+                }
+                request_response::Event::Message {
+                    peer,
+                    message: request_response::Message::Response { response, .. },
+                } if !response.accepted => {
+                    error!("Direct: {:?} did not take a message", peer);
+                }
+                request_response::Event::OutboundFailure { peer, error, .. } => {
+                    error!("Direct: failed to send to {:?}: {:?}", peer, error);
+                }
+                _ => {
+                    debug!("Direct: {:?}", event);
+                }
+            },
+            PeerAdminEvent::History(event) => match event {
+                request_response::Event::Message {
+                    peer,
+                    message:
+                        request_response::Message::Request {
+                            request, channel, ..
+                        },
+                } => {
+                    let workspace = self
+                        .workspaces
+                        .get(&TopicHash::from_raw(request.topic.clone()));
+                    if workspace.is_some_and(|w| !w.access.allows(&peer))
+                        && !self.is_admin_peer(&peer)
+                    {
+                        // Dropping the channel fails the request on the peer's side.
+                        warn!(
+                            "History: peer {:?} is not allowed in {:?}",
+                            peer, request.topic
+                        );
+                        return;
+                    }
+                    let (entries, expired): (Vec<_>, Vec<_>) = workspace
+                        .map(|w| w.history.since(&request.topic, request.since))
+                        .unwrap_or_default()
+                        .into_iter()
+                        .partition(|e| {
+                            !e.message.is_expired(unix_millis(), self.config.clock_skew)
+                        });
+                    self.metrics.count_expired_replayed(expired.len());
+                    info!(
+                        "History: replaying {} messages of {:?} to {:?}",
+                        entries.len(),
+                        request.topic,
+                        peer
+                    );
+                    if self
+                        .swarm
+                        .behaviour_mut()
+                        .history
+                        .send_response(channel, HistoryResponse { entries })
+                        .is_err()
+                    {
+                        error!("History: failed to reply to {:?}", peer);
+                    }
+                }
+                _ => {
+                    debug!("History: {:?}", event);
+                }
+            },
 
             PeerAdminEvent::GossipSub(event) => match event {
+                gossipsub::Event::Unsubscribed { peer_id, .. } if self.is_admin_peer(&peer_id) => {
+                    debug!("GossipSub: admin {:?} unsubscribed", peer_id);
+                }
+                gossipsub::Event::Subscribed { peer_id, .. } if self.is_admin_peer(&peer_id) => {
+                    debug!("GossipSub: admin {:?} subscribed", peer_id);
+                }
                 gossipsub::Event::Unsubscribed { topic, peer_id } => {
                     info!(
                         "GossipSub: Unsubscribed to topic {:?} from peer: {:?}",
                         topic, peer_id
                     );
-                    self.outside_tx
-                        .send(NodeMessage::Event {
-                            time: std::time::SystemTime::now()
-                                .duration_since(std::time::UNIX_EPOCH)
-                                .unwrap()
-                                .as_secs_f64() as u64,
-                            created_by: peer_id.to_string(),
-                            event: EventType::Unsubscribe {
+                    let was_member = self.connected_peers.get_mut(&topic).is_some_and(|peers| {
+                        let count = peers.len();
+                        peers.retain(|p| p != &peer_id);
+                        peers.len() < count
+                    });
+                    if was_member {
+                        let event = NodeMessage::new_event(
+                            &mut self.clock,
+                            peer_id.to_string(),
+                            EventType::Unsubscribe {
                                 topic: topic.to_string(),
                                 peer_id: peer_id.to_string(),
                             },
-                        })
-                        .await
-                        .expect("Outside tx failed");
-
-                    let peers = self.connected_peers.get_mut(&topic);
-                    if let Some(peers) = peers {
-                        peers.retain(|p| p != &peer_id);
+                        );
+                        self.send_outside(&topic, event).await;
                     }
                 }
                 gossipsub::Event::Subscribed { topic, peer_id } => {
@@ -191,33 +1224,122 @@ impl AdminPeer {
                         "GossipSub: Subscribed to topic {:?} from peer: {:?}",
                         topic, peer_id
                     );
-                    self.connected_peers
-                        .get_mut(&topic)
-                        .unwrap_or(&mut vec![])
-                        .push(peer_id);
-                    self.outside_tx
-                        .send(NodeMessage::Event {
-                            time: std::time::SystemTime::now()
-                                .duration_since(std::time::UNIX_EPOCH)
-                                .unwrap()
-                                .as_secs_f64() as u64,
-                            created_by: peer_id.to_string(),
-                            event: EventType::Subscribe {
-                                topic: topic.to_string(),
-                                peer_id: peer_id.to_string(),
-                            },
-                        })
-                        .await
-                        .expect("Outside tx failed");
-                }
-                gossipsub::Event::Message { message, .. } => {
-                    let msg = NodeMessage::from_bytes(message.data);
-                    match self.outside_tx.send(msg).await {
-                        Ok(_) => {}
-                        Err(e) => {
-                            error!("Failed to send message to outside: {:?}", e);
-                        }
+                    // Either lane of a workspace is checked, both carry its traffic.
+                    let Some(workspace) = self
+                        .workspace_carrying(&topic)
+                        .and_then(|carrier| self.workspaces.get(&carrier))
+                    else {
+                        debug!("GossipSub: {:?} is not a hosted workspace", topic);
+                        return;
                     };
+                    if !workspace.access.allows(&peer_id) {
+                        // Gossipsub would still forward the workspace traffic to a subscriber,
+                        // so a peer joining a workspace it is denied is cut off altogether.
+                        warn!(
+                            "Peer {:?} is not allowed in workspace {}, disconnecting it",
+                            peer_id, workspace.workspace_id
+                        );
+                        self.swarm
+                            .behaviour_mut()
+                            .gossip_sub
+                            .blacklist_peer(&peer_id);
+                        let _ = self.swarm.disconnect_peer_id(peer_id);
+                        return;
+                    }
+                    if !self.workspaces.contains_key(&topic) {
+                        return;
+                    }
+                    let peers = self.connected_peers.entry(topic.clone()).or_default();
+                    if !peers.contains(&peer_id) {
+                        peers.push(peer_id);
+                    }
+                    let event = NodeMessage::new_event(
+                        &mut self.clock,
+                        peer_id.to_string(),
+                        EventType::Subscribe {
+                            topic: topic.to_string(),
+                            peer_id: peer_id.to_string(),
+                        },
+                    );
+                    self.send_outside(&topic, event).await;
+                }
+                gossipsub::Event::Message {
+                    propagation_source,
+                    message_id,
+                    message,
+                } if message.topic.as_str() == admin_topic(&self.config.workspace_id) => {
+                    let heartbeat = self.config.failover.as_ref().and_then(|failover| {
+                        AdminHeartbeat::from_admin(&message.data, message.source.as_ref(), failover)
+                    });
+                    let acceptance = if heartbeat.is_some() {
+                        MessageAcceptance::Accept
+                    } else {
+                        MessageAcceptance::Reject
+                    };
+                    report_validation(
+                        &mut self.swarm.behaviour_mut().gossip_sub,
+                        &message_id,
+                        &propagation_source,
+                        acceptance,
+                    );
+                    if let Some(heartbeat) = heartbeat {
+                        self.on_admin_heartbeat(heartbeat).await;
+                    }
+                }
+                gossipsub::Event::Message {
+                    propagation_source,
+                    message_id,
+                    message,
+                } => {
+                    let Some(topic) = self.workspace_carrying(&message.topic) else {
+                        report_validation(
+                            &mut self.swarm.behaviour_mut().gossip_sub,
+                            &message_id,
+                            &propagation_source,
+                            MessageAcceptance::Ignore,
+                        );
+                        return;
+                    };
+                    let workspace = &self.workspaces[&topic];
+                    let author = message.source.unwrap_or(propagation_source);
+                    // Banned peers stay connected for a grace period, nothing they send counts.
+                    if self.bans.contains(&author) || self.bans.contains(&propagation_source) {
+                        warn!("Rejecting message from banned peer {:?}", author);
+                        report_validation(
+                            &mut self.swarm.behaviour_mut().gossip_sub,
+                            &message_id,
+                            &propagation_source,
+                            MessageAcceptance::Reject,
+                        );
+                        return;
+                    }
+                    if !workspace.access.allows(&author) && !self.is_admin_peer(&author) {
+                        warn!(
+                            "Peer {:?} is not allowed in workspace {}",
+                            author, workspace.workspace_id
+                        );
+                        report_validation(
+                            &mut self.swarm.behaviour_mut().gossip_sub,
+                            &message_id,
+                            &propagation_source,
+                            MessageAcceptance::Reject,
+                        );
+                        return;
+                    }
+                    let pending = PendingValidation {
+                        route: Route::Gossip {
+                            message_id,
+                            propagation_source,
+                        },
+                        message,
+                        validator: workspace.validator.clone(),
+                    };
+                    if let Err(pending) = self.validation.submit(pending) {
+                        pending.route.report(
+                            &mut self.swarm.behaviour_mut().gossip_sub,
+                            MessageAcceptance::Ignore,
+                        );
+                    }
                 }
                 _ => {
                     info!("GossipSub: {:?}", event);
@@ -226,3 +1348,96 @@ impl AdminPeer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use libp2p::{gossipsub, PeerId};
+    use tokio_util::sync::CancellationToken;
+
+    use crate::peer::message::data::OutgoingMessage;
+    use crate::peer::node::test_support::{join, received, run_admin, run_member};
+    use crate::peer::node::{create_key, AdminPeer, AdminPeerConfig, Emitter, WorkspaceConfig};
+
+    async fn send_to(emitter: &Emitter, to: &str, payload: &[u8]) {
+        let message = OutgoingMessage {
+            to: Some(PeerId::from_str(to).unwrap()),
+            ..payload.to_vec().into()
+        };
+        emitter.send(message).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn notices_about_a_peer_stay_in_its_workspaces() {
+        let (mut admin, _admin_rx) =
+            AdminPeer::create(AdminPeerConfig::new(0, "first".to_string()), create_key())
+                .await
+                .unwrap();
+        admin
+            .host_workspace(WorkspaceConfig::new("second".to_string()))
+            .unwrap();
+        let second = gossipsub::IdentTopic::new("second").hash();
+        let peer = PeerId::random();
+        assert!(admin.workspaces_of(&peer).is_empty());
+        admin
+            .connected_peers
+            .entry(second.clone())
+            .or_default()
+            .push(peer);
+        assert_eq!(admin.workspaces_of(&peer), vec![second]);
+    }
+
+    #[tokio::test]
+    async fn addressed_messages_reach_their_recipient_alone() {
+        let workspace = "direct";
+        let cancel = CancellationToken::new();
+
+        let (admin, mut admin_rx) =
+            AdminPeer::create(AdminPeerConfig::new(0, workspace.to_string()), create_key())
+                .await
+                .unwrap();
+        let admin_id = admin.id.clone();
+        let admin_emitter = admin.emitter();
+        let port = run_admin(admin, &cancel).await;
+
+        let (first_id, first, mut first_rx) =
+            run_member("first", workspace, &admin_id, port, &cancel).await;
+        join(&first, &mut admin_rx).await;
+        let (second_id, second, mut second_rx) =
+            run_member("second", workspace, &admin_id, port, &cancel).await;
+        join(&second, &mut admin_rx).await;
+        received(&mut first_rx, Duration::from_millis(500)).await;
+        received(&mut second_rx, Duration::from_millis(500)).await;
+
+        send_to(&first, &second_id, b"relayed").await;
+        assert_eq!(
+            received(&mut second_rx, Duration::from_secs(1)).await,
+            vec![b"relayed".to_vec()]
+        );
+        assert!(received(&mut admin_rx, Duration::from_millis(250))
+            .await
+            .is_empty());
+
+        send_to(&admin_emitter, &first_id, b"from admin").await;
+        assert_eq!(
+            received(&mut first_rx, Duration::from_secs(1)).await,
+            vec![b"from admin".to_vec()]
+        );
+        assert!(received(&mut second_rx, Duration::from_millis(250))
+            .await
+            .is_empty());
+
+        send_to(&first, &admin_id, b"to admin").await;
+        assert_eq!(
+            received(&mut admin_rx, Duration::from_secs(1)).await,
+            vec![b"to admin".to_vec()]
+        );
+        assert!(received(&mut second_rx, Duration::from_millis(250))
+            .await
+            .is_empty());
+
+        cancel.cancel();
+    }
+}
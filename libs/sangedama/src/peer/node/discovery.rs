@@ -0,0 +1,30 @@
+use libp2p::{kad, rendezvous};
+use sha2::{Digest, Sha256};
+
+use crate::peer::node::failover::AdminEndpoint;
+
+#[derive(Debug, Clone, Default)]
+pub struct DhtConfig {
+    /// Any nodes already part of the DHT, admins or members alike.
+    pub bootstrap: Vec<AdminEndpoint>,
+}
+
+impl DhtConfig {
+    pub fn new(bootstrap: Vec<AdminEndpoint>) -> Self {
+        Self { bootstrap }
+    }
+}
+
+/// The key an admin advertises itself under as provider of a workspace.
+pub(crate) fn workspace_key(workspace_id: &str) -> kad::RecordKey {
+    kad::RecordKey::new(&format!("/ceylon/workspace/{}", workspace_id))
+}
+
+/// The rendezvous namespace members of a workspace register under, hashed when the id is too long.
+pub(crate) fn rendezvous_namespace(workspace_id: &str) -> rendezvous::Namespace {
+    rendezvous::Namespace::new(format!("ceylon/{}", workspace_id)).unwrap_or_else(|_| {
+        let digest = Sha256::digest(workspace_id.as_bytes());
+        let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        rendezvous::Namespace::new(format!("ceylon/{}", hex)).unwrap()
+    })
+}
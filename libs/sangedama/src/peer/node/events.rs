@@ -0,0 +1,167 @@
+use libp2p::swarm::SwarmEvent;
+use libp2p::{gossipsub, rendezvous, Multiaddr, PeerId};
+use tokio::sync::broadcast;
+
+/// Capacity of the network event stream, slow subscribers skip what they missed.
+pub(crate) const NETWORK_EVENT_CAPACITY: usize = 256;
+
+/// Lifecycle and network events of a peer, apart from the messages it delivers.
+#[derive(Debug, Clone)]
+pub enum NetworkEvent {
+    ListeningOn {
+        address: Multiaddr,
+    },
+    ListenAddressExpired {
+        address: Multiaddr,
+    },
+    ConnectionEstablished {
+        peer_id: PeerId,
+        address: Multiaddr,
+    },
+    ConnectionClosed {
+        peer_id: PeerId,
+        address: Multiaddr,
+        cause: Option<String>,
+    },
+    DialFailed {
+        peer_id: Option<PeerId>,
+        error: String,
+    },
+    /// We registered at a rendezvous point.
+    Registered {
+        rendezvous_node: PeerId,
+        namespace: String,
+        ttl: u64,
+    },
+    RegistrationFailed {
+        rendezvous_node: PeerId,
+        namespace: String,
+        error: String,
+    },
+    /// A peer registered with us as rendezvous point.
+    PeerRegistered {
+        peer_id: PeerId,
+        namespace: String,
+    },
+    PeerUnregistered {
+        peer_id: PeerId,
+        namespace: String,
+    },
+    PeerRegistrationExpired {
+        peer_id: PeerId,
+        namespace: String,
+    },
+    Subscribed {
+        peer_id: PeerId,
+        topic: String,
+    },
+    Unsubscribed {
+        peer_id: PeerId,
+        topic: String,
+    },
+}
+
+impl NetworkEvent {
+    pub(crate) fn from_swarm<E>(event: &SwarmEvent<E>) -> Option<Self> {
+        match event {
+            SwarmEvent::NewListenAddr { address, .. } => Some(NetworkEvent::ListeningOn {
+                address: address.clone(),
+            }),
+            SwarmEvent::ExpiredListenAddr { address, .. } => {
+                Some(NetworkEvent::ListenAddressExpired {
+                    address: address.clone(),
+                })
+            }
+            SwarmEvent::ConnectionEstablished {
+                peer_id, endpoint, ..
+            } => Some(NetworkEvent::ConnectionEstablished {
+                peer_id: *peer_id,
+                address: endpoint.get_remote_address().clone(),
+            }),
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                endpoint,
+                cause,
+                ..
+            } => Some(NetworkEvent::ConnectionClosed {
+                peer_id: *peer_id,
+                address: endpoint.get_remote_address().clone(),
+                cause: cause.as_ref().map(|c| c.to_string()),
+            }),
+            SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
+                Some(NetworkEvent::DialFailed {
+                    peer_id: *peer_id,
+                    error: error.to_string(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn from_gossipsub(event: &gossipsub::Event) -> Option<Self> {
+        match event {
+            gossipsub::Event::Subscribed { peer_id, topic } => Some(NetworkEvent::Subscribed {
+                peer_id: *peer_id,
+                topic: topic.to_string(),
+            }),
+            gossipsub::Event::Unsubscribed { peer_id, topic } => Some(NetworkEvent::Unsubscribed {
+                peer_id: *peer_id,
+                topic: topic.to_string(),
+            }),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn from_rendezvous_server(event: &rendezvous::server::Event) -> Option<Self> {
+        match event {
+            rendezvous::server::Event::PeerRegistered { peer, registration } => {
+                Some(NetworkEvent::PeerRegistered {
+                    peer_id: *peer,
+                    namespace: registration.namespace.to_string(),
+                })
+            }
+            rendezvous::server::Event::PeerUnregistered { peer, namespace } => {
+                Some(NetworkEvent::PeerUnregistered {
+                    peer_id: *peer,
+                    namespace: namespace.to_string(),
+                })
+            }
+            rendezvous::server::Event::RegistrationExpired(registration) => {
+                Some(NetworkEvent::PeerRegistrationExpired {
+                    peer_id: registration.record.peer_id(),
+                    namespace: registration.namespace.to_string(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn from_rendezvous_client(event: &rendezvous::client::Event) -> Option<Self> {
+        match event {
+            rendezvous::client::Event::Registered {
+                rendezvous_node,
+                ttl,
+                namespace,
+            } => Some(NetworkEvent::Registered {
+                rendezvous_node: *rendezvous_node,
+                namespace: namespace.to_string(),
+                ttl: *ttl,
+            }),
+            rendezvous::client::Event::RegisterFailed {
+                rendezvous_node,
+                namespace,
+                error,
+            } => Some(NetworkEvent::RegistrationFailed {
+                rendezvous_node: *rendezvous_node,
+                namespace: namespace.to_string(),
+                error: format!("{:?}", error),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Sends to whoever subscribed, the event is dropped when nobody did.
+pub(crate) fn emit(tx: &broadcast::Sender<NetworkEvent>, event: NetworkEvent) {
+    let _ = tx.send(event);
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+
+/// An admin that can act as rendezvous point and hub for a workspace.
+#[derive(Debug, Clone)]
+pub struct AdminEndpoint {
+    pub peer_id: PeerId,
+    pub address: Multiaddr,
+}
+
+impl AdminEndpoint {
+    pub fn new(peer_id: PeerId, address: Multiaddr) -> Self {
+        Self { peer_id, address }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FailoverConfig {
+    /// Every admin of the workspace ordered by precedence, the primary first.
+    /// An admin finds its own rank by looking up its peer id in this list.
+    pub admins: Vec<AdminEndpoint>,
+    pub heartbeat_interval: Duration,
+    /// How long a standby waits without heartbeat before taking over.
+    /// Lower ranked standbys wait a multiple of it, so only one takes over.
+    pub failover_timeout: Duration,
+    /// Extra time a standby that has not seen any heartbeat since it started gives the
+    /// primary before its failover countdown begins.
+    pub startup_grace: Duration,
+}
+
+impl FailoverConfig {
+    pub fn new(admins: Vec<AdminEndpoint>) -> Self {
+        Self {
+            admins,
+            heartbeat_interval: Duration::from_secs(1),
+            failover_timeout: Duration::from_secs(5),
+            startup_grace: Duration::from_secs(5),
+        }
+    }
+
+    pub fn rank_of(&self, peer_id: &PeerId) -> Option<usize> {
+        self.admins.iter().position(|a| &a.peer_id == peer_id)
+    }
+
+    pub fn is_admin(&self, peer_id: &PeerId) -> bool {
+        self.rank_of(peer_id).is_some()
+    }
+}
+
+pub(crate) fn admin_topic(workspace_id: &str) -> String {
+    format!("{}-admins", workspace_id)
+}
+
+/// Published by the primary admin on the admin topic so standbys can replicate its roster
+/// and notice when it goes away.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct AdminHeartbeat {
+    pub admin: String,
+    pub rank: usize,
+    pub time: u64,
+    pub roster: HashMap<String, Vec<String>>,
+}
+
+impl AdminHeartbeat {
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        serde_json::from_slice(bytes).ok()
+    }
+
+    /// The heartbeat in `bytes` when `source`, the peer that signed it, is the admin it names
+    /// and has the rank it claims. Anyone else on the admin topic could forge the roster.
+    pub fn from_admin(
+        bytes: &[u8],
+        source: Option<&PeerId>,
+        failover: &FailoverConfig,
+    ) -> Option<Self> {
+        let heartbeat = Self::from_bytes(bytes)?;
+        let source = source?;
+        (heartbeat.admin == source.to_string() && failover.rank_of(source) == Some(heartbeat.rank))
+            .then_some(heartbeat)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn endpoint() -> AdminEndpoint {
+        AdminEndpoint::new(
+            PeerId::random(),
+            Multiaddr::from_str("/ip4/127.0.0.1/udp/7000/quic-v1").unwrap(),
+        )
+    }
+
+    fn heartbeat(admin: &PeerId, rank: usize) -> Vec<u8> {
+        AdminHeartbeat {
+            admin: admin.to_string(),
+            rank,
+            time: 0,
+            roster: HashMap::new(),
+        }
+        .to_bytes()
+    }
+
+    #[test]
+    fn heartbeats_are_only_taken_from_the_admin_they_name() {
+        let (primary, standby) = (endpoint(), endpoint());
+        let failover = FailoverConfig::new(vec![primary.clone(), standby.clone()]);
+        let member = PeerId::random();
+
+        let bytes = heartbeat(&primary.peer_id, 0);
+        assert!(AdminHeartbeat::from_admin(&bytes, Some(&primary.peer_id), &failover).is_some());
+        // Published by someone else in the name of the primary.
+        assert!(AdminHeartbeat::from_admin(&bytes, Some(&member), &failover).is_none());
+        assert!(AdminHeartbeat::from_admin(&bytes, None, &failover).is_none());
+        // A member claiming precedence under its own name.
+        let bytes = heartbeat(&member, 0);
+        assert!(AdminHeartbeat::from_admin(&bytes, Some(&member), &failover).is_none());
+        // A standby claiming the rank of the primary.
+        let bytes = heartbeat(&standby.peer_id, 0);
+        assert!(AdminHeartbeat::from_admin(&bytes, Some(&standby.peer_id), &failover).is_none());
+    }
+}
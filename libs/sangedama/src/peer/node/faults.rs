@@ -0,0 +1,316 @@
+//! Network faults injected into the traffic a peer receives, so that retry and failover
+//! logic can be exercised without a real broken network. Only built with the
+//! `fault-injection` feature, without it peers carry a no-op layer.
+//!
+//! Latency, loss, duplication and reordering only apply to gossipsub messages this peer
+//! receives. What it sends, gossipsub control traffic, history replay, rendezvous and the
+//! other protocols pass untouched, so fault both ends to degrade a link both ways.
+//! Partitions are the exception, they cut whole connections.
+
+#[cfg(feature = "fault-injection")]
+pub(crate) use injector::FaultLayer;
+#[cfg(feature = "fault-injection")]
+pub use injector::{FaultInjector, LinkFaults};
+#[cfg(not(feature = "fault-injection"))]
+pub(crate) use passthrough::FaultLayer;
+
+use libp2p::{gossipsub, PeerId};
+
+#[cfg_attr(not(feature = "fault-injection"), allow(dead_code))]
+pub(crate) enum FaultEvent {
+    /// A message held back by latency, jitter, reordering or duplication.
+    Delayed(Box<gossipsub::Event>),
+    /// The peers partitioned away, sent whenever the faults change.
+    Partitioned(Vec<PeerId>),
+}
+
+#[cfg(feature = "fault-injection")]
+mod injector {
+    use std::collections::{HashMap, HashSet};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use libp2p::{gossipsub, PeerId};
+    use rand::Rng;
+    use tokio::sync::{mpsc, watch};
+    use tracing::debug;
+
+    use super::FaultEvent;
+
+    /// Faults applied to messages received from a peer.
+    #[derive(Debug, Clone, Default)]
+    pub struct LinkFaults {
+        pub latency: Duration,
+        /// Random extra delay, up to this much.
+        pub jitter: Duration,
+        /// Probability a message is lost, between 0 and 1.
+        pub loss: f64,
+        /// Probability a message is delivered twice.
+        pub duplication: f64,
+        /// Probability a message is held back by `reorder_delay`, letting later ones overtake it.
+        pub reordering: f64,
+        pub reorder_delay: Duration,
+    }
+
+    impl LinkFaults {
+        pub fn new() -> Self {
+            Self {
+                reorder_delay: Duration::from_millis(100),
+                ..Default::default()
+            }
+        }
+
+        fn delay(&self, rng: &mut impl Rng) -> Duration {
+            let mut delay = self.latency + self.jitter.mul_f64(rng.gen::<f64>());
+            if rng.gen_bool(self.reordering.clamp(0.0, 1.0)) {
+                delay += self.reorder_delay;
+            }
+            delay
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct FaultState {
+        default: Option<LinkFaults>,
+        links: HashMap<PeerId, LinkFaults>,
+        partitioned: HashSet<PeerId>,
+    }
+
+    impl FaultState {
+        // When each copy of a message from the peer is delivered, empty when it is lost.
+        fn plan(&self, peer_id: &PeerId) -> Vec<Duration> {
+            if self.partitioned.contains(peer_id) {
+                return vec![];
+            }
+            let Some(link) = self.links.get(peer_id).or(self.default.as_ref()) else {
+                return vec![Duration::ZERO];
+            };
+            let mut rng = rand::thread_rng();
+            if rng.gen_bool(link.loss.clamp(0.0, 1.0)) {
+                return vec![];
+            }
+            let copies = if rng.gen_bool(link.duplication.clamp(0.0, 1.0)) {
+                2
+            } else {
+                1
+            };
+            (0..copies).map(|_| link.delay(&mut rng)).collect()
+        }
+    }
+
+    /// Runtime control over the faults a peer applies, cheap to clone.
+    #[derive(Debug, Clone)]
+    pub struct FaultInjector {
+        state: Arc<watch::Sender<FaultState>>,
+    }
+
+    impl FaultInjector {
+        /// Faults for every peer without faults of its own.
+        pub fn set_default(&self, faults: LinkFaults) {
+            self.state.send_modify(|state| state.default = Some(faults));
+        }
+
+        pub fn set_link(&self, peer_id: PeerId, faults: LinkFaults) {
+            self.state.send_modify(|state| {
+                state.links.insert(peer_id, faults);
+            });
+        }
+
+        pub fn clear_link(&self, peer_id: &PeerId) {
+            self.state.send_modify(|state| {
+                state.links.remove(peer_id);
+            });
+        }
+
+        /// Cuts the connections to these peers and refuses new ones until healed.
+        pub fn partition(&self, peers: impl IntoIterator<Item = PeerId>) {
+            self.state
+                .send_modify(|state| state.partitioned.extend(peers));
+        }
+
+        pub fn heal(&self) {
+            self.state.send_modify(|state| state.partitioned.clear());
+        }
+
+        /// Removes every fault.
+        pub fn reset(&self) {
+            self.state
+                .send_modify(|state| *state = FaultState::default());
+        }
+    }
+
+    pub(crate) struct FaultLayer {
+        injector: FaultInjector,
+        state: watch::Receiver<FaultState>,
+        delayed_tx: mpsc::UnboundedSender<gossipsub::Event>,
+        delayed_rx: mpsc::UnboundedReceiver<gossipsub::Event>,
+    }
+
+    impl FaultLayer {
+        pub(crate) fn new() -> Self {
+            let (state_tx, state) = watch::channel(FaultState::default());
+            let (delayed_tx, delayed_rx) = mpsc::unbounded_channel();
+            Self {
+                injector: FaultInjector {
+                    state: Arc::new(state_tx),
+                },
+                state,
+                delayed_tx,
+                delayed_rx,
+            }
+        }
+
+        pub(crate) fn injector(&self) -> FaultInjector {
+            self.injector.clone()
+        }
+
+        /// The event to handle right away, delayed copies come out of `delayed`.
+        pub(crate) fn inbound(&self, event: gossipsub::Event) -> Option<gossipsub::Event> {
+            let gossipsub::Event::Message {
+                propagation_source,
+                message_id,
+                message,
+            } = event
+            else {
+                return Some(event);
+            };
+            let delays = self.state.borrow().plan(&propagation_source);
+            if delays.is_empty() {
+                debug!("Fault injection lost message {}", message_id);
+            }
+            let mut immediate = None;
+            for delay in delays {
+                let event = gossipsub::Event::Message {
+                    propagation_source,
+                    message_id: message_id.clone(),
+                    message: message.clone(),
+                };
+                if delay.is_zero() && immediate.is_none() {
+                    immediate = Some(event);
+                    continue;
+                }
+                let delayed_tx = self.delayed_tx.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    let _ = delayed_tx.send(event);
+                });
+            }
+            immediate
+        }
+
+        /// A delayed copy of a message, or the partitioned peers after the faults changed.
+        pub(crate) async fn next(&mut self) -> FaultEvent {
+            // Neither channel closes, the layer keeps both senders.
+            tokio::select! {
+                Some(event) = self.delayed_rx.recv() => FaultEvent::Delayed(Box::new(event)),
+                Ok(()) = self.state.changed() => {
+                    let partitioned = self.state.borrow_and_update().partitioned.iter().copied().collect();
+                    FaultEvent::Partitioned(partitioned)
+                }
+            }
+        }
+
+        pub(crate) fn is_partitioned(&self, peer_id: &PeerId) -> bool {
+            self.state.borrow().partitioned.contains(peer_id)
+        }
+    }
+}
+
+#[cfg(not(feature = "fault-injection"))]
+mod passthrough {
+    use libp2p::{gossipsub, PeerId};
+
+    use super::FaultEvent;
+
+    pub(crate) struct FaultLayer;
+
+    impl FaultLayer {
+        pub(crate) fn new() -> Self {
+            Self
+        }
+
+        pub(crate) fn inbound(&self, event: gossipsub::Event) -> Option<gossipsub::Event> {
+            Some(event)
+        }
+
+        pub(crate) async fn next(&mut self) -> FaultEvent {
+            std::future::pending().await
+        }
+
+        pub(crate) fn is_partitioned(&self, _peer_id: &PeerId) -> bool {
+            false
+        }
+    }
+}
+
+#[cfg(all(test, feature = "fault-injection"))]
+mod tests {
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use libp2p::PeerId;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::peer::node::test_support::{join, received, run_admin, run_member};
+    use crate::peer::node::{create_key, AdminPeer, AdminPeerConfig, Emitter, LinkFaults};
+
+    async fn send(emitter: &Emitter, payload: &[u8]) {
+        emitter.send(payload.to_vec()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn faults_apply_to_messages_between_two_peers() {
+        let workspace = "faults";
+        let cancel = CancellationToken::new();
+
+        let (admin, mut admin_rx) =
+            AdminPeer::create(AdminPeerConfig::new(0, workspace.to_string()), create_key())
+                .await
+                .unwrap();
+        let admin_id = admin.id.clone();
+        let injector = admin.fault_injector();
+        let port = run_admin(admin, &cancel).await;
+
+        let (member_id, emitter, _member_rx) =
+            run_member("member", workspace, &admin_id, port, &cancel).await;
+        let member_id = PeerId::from_str(&member_id).unwrap();
+        join(&emitter, &mut admin_rx).await;
+
+        injector.set_link(
+            member_id,
+            LinkFaults {
+                loss: 1.0,
+                ..LinkFaults::new()
+            },
+        );
+        for _ in 0..3 {
+            send(&emitter, b"lost").await;
+        }
+        assert!(received(&mut admin_rx, Duration::from_secs(1))
+            .await
+            .is_empty());
+
+        injector.set_link(
+            member_id,
+            LinkFaults {
+                duplication: 1.0,
+                ..LinkFaults::new()
+            },
+        );
+        send(&emitter, b"twice").await;
+        assert_eq!(
+            received(&mut admin_rx, Duration::from_secs(1)).await,
+            vec![b"twice".to_vec(), b"twice".to_vec()]
+        );
+
+        injector.clear_link(&member_id);
+        injector.partition([member_id]);
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        send(&emitter, b"partitioned").await;
+        assert!(received(&mut admin_rx, Duration::from_secs(1))
+            .await
+            .is_empty());
+
+        cancel.cancel();
+    }
+}
@@ -0,0 +1,35 @@
+use tokio::sync::mpsc;
+
+use crate::peer::message::data::{OutgoingMessage, Priority};
+
+/// The topic control messages of a workspace travel on, apart from its bulk data.
+pub(crate) fn control_topic(workspace_id: &str) -> String {
+    format!("{}-control", workspace_id)
+}
+
+/// Queues outgoing messages in the lane of their priority, the control lane is always sent first.
+#[derive(Debug, Clone)]
+pub struct Emitter {
+    data: mpsc::Sender<OutgoingMessage>,
+    control: mpsc::Sender<OutgoingMessage>,
+}
+
+impl Emitter {
+    pub(crate) fn new(
+        data: mpsc::Sender<OutgoingMessage>,
+        control: mpsc::Sender<OutgoingMessage>,
+    ) -> Self {
+        Self { data, control }
+    }
+
+    pub async fn send(
+        &self,
+        message: impl Into<OutgoingMessage>,
+    ) -> Result<(), mpsc::error::SendError<OutgoingMessage>> {
+        let message = message.into();
+        match message.priority {
+            Priority::Control => self.control.send(message).await,
+            Priority::Data => self.data.send(message).await,
+        }
+    }
+}
@@ -1,5 +1,9 @@
+use std::collections::HashSet;
 use std::net::Ipv4Addr;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
 use futures::StreamExt;
 use libp2p::multiaddr::Protocol;
@@ -7,23 +11,72 @@ use libp2p::swarm::{
     dial_opts::{DialOpts, PeerCondition},
     SwarmEvent,
 };
-use libp2p::{gossipsub, identity, rendezvous, Multiaddr, PeerId, Swarm};
+use libp2p::{
+    gossipsub, identify, identity, kad, rendezvous, request_response, Multiaddr, PeerId, Swarm,
+};
 use tokio::select;
+use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn, Instrument};
 
-use crate::peer::behaviour::{ClientPeerBehaviour, ClientPeerEvent};
-use crate::peer::message::data::NodeMessage;
+use crate::peer::behaviour::{ClientPeerBehaviour, ClientPeerEvent, MIN_REGISTRATION_TTL};
+use crate::peer::message::clock::{unix_millis, HybridClock};
+use crate::peer::message::compression::{CompressionConfig, CompressionPeers};
+use crate::peer::message::data::{
+    EventType, NodeMessage, OutgoingMessage, Priority, DEFAULT_CLOCK_SKEW,
+};
+use crate::peer::message::direct::{DirectRequest, DirectResponse};
+use crate::peer::message::history::{HistoryCursor, HistoryRequest};
+use crate::peer::message::recording::{open_recorder, Direction, MessageRecorder};
+use crate::peer::message::validation::{
+    MessageAcceptance, MessageValidator, PendingValidation, Route, Validated, ValidationQueue,
+};
+use crate::peer::node::discovery::{rendezvous_namespace, workspace_key, DhtConfig};
+use crate::peer::node::events::{emit, NetworkEvent, NETWORK_EVENT_CAPACITY};
+use crate::peer::node::failover::AdminEndpoint;
+#[cfg(feature = "fault-injection")]
+use crate::peer::node::faults::FaultInjector;
+use crate::peer::node::faults::{FaultEvent, FaultLayer};
+use crate::peer::node::lanes::{control_topic, Emitter};
+use crate::peer::node::metrics::PeerMetrics;
+use crate::peer::node::scoring::{Graylist, PeerScoreConfig, SCORE_CHECK_INTERVAL};
+use crate::peer::node::shutdown::{close_connections, ShutdownConfig, ShutdownSummary};
 use crate::peer::peer_swarm::create_swarm;
 
 #[derive(Debug, Clone)]
 pub struct MemberPeerConfig {
     pub name: String,
     pub workspace_id: String,
-    pub admin_peer: PeerId,
-    pub rendezvous_point_address: Multiaddr,
+    /// The admin to join, `None` when it is looked up in the DHT.
+    pub admin: Option<AdminEndpoint>,
+    /// Ask the admin to replay the workspace history from this cursor once it sees us.
+    pub history_replay: Option<HistoryCursor>,
+    /// Admins to fall back to, in order, when the current one goes away.
+    pub standby_admins: Vec<AdminEndpoint>,
+    /// Find the admin as provider of the workspace in the DHT.
+    pub dht: Option<DhtConfig>,
+    /// Score peers and graylist the ones misbehaving on the workspace topic.
+    pub peer_score: Option<PeerScoreConfig>,
+    /// Seconds the admin keeps our registration, `None` for its default.
+    /// The registration is renewed before it runs out. Admins refuse ttls below
+    /// [`MIN_REGISTRATION_TTL`], so [`MemberPeer::create`] does too.
+    pub registration_ttl: Option<u64>,
+    /// Compress large outgoing payloads while every peer on the topic can read them,
+    /// incoming ones are read either way.
+    pub compression: Option<CompressionConfig>,
+    /// TTL of the messages broadcast without one of their own.
+    pub message_ttl: Option<Duration>,
+    /// How far the clocks of senders may run behind ours before their messages look expired.
+    pub clock_skew: Duration,
+    /// JSON lines file every message sent and received is appended to, see
+    /// [`read_recording`](crate::peer::message::recording::read_recording).
+    pub recording: Option<PathBuf>,
+    pub shutdown: ShutdownConfig,
 }
 
+const ADMIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const REGISTRATION_RETRY_DELAY: Duration = Duration::from_secs(5);
+
 impl MemberPeerConfig {
     pub fn new(
         name: String,
@@ -39,8 +92,38 @@ impl MemberPeerConfig {
         Self {
             name,
             workspace_id,
-            admin_peer: PeerId::from_str(&admin_peer).unwrap(),
-            rendezvous_point_address,
+            admin: Some(AdminEndpoint::new(
+                PeerId::from_str(&admin_peer).unwrap(),
+                rendezvous_point_address,
+            )),
+            history_replay: None,
+            standby_admins: vec![],
+            dht: None,
+            peer_score: None,
+            registration_ttl: None,
+            compression: None,
+            message_ttl: None,
+            clock_skew: DEFAULT_CLOCK_SKEW,
+            recording: None,
+            shutdown: ShutdownConfig::default(),
+        }
+    }
+
+    pub fn new_with_dht(name: String, workspace_id: String, dht: DhtConfig) -> Self {
+        Self {
+            name,
+            workspace_id,
+            admin: None,
+            history_replay: None,
+            standby_admins: vec![],
+            dht: Some(dht),
+            peer_score: None,
+            registration_ttl: None,
+            compression: None,
+            message_ttl: None,
+            clock_skew: DEFAULT_CLOCK_SKEW,
+            recording: None,
+            shutdown: ShutdownConfig::default(),
         }
     }
 }
@@ -50,42 +133,172 @@ pub struct MemberPeer {
     pub id: String,
     swarm: Swarm<ClientPeerBehaviour>,
 
+    admins: Vec<AdminEndpoint>,
+    admin_index: usize,
+    registered_admin: Option<PeerId>,
+    providers: HashSet<PeerId>,
+    reconnect_at: Option<Instant>,
+    register_at: Option<Instant>,
+    history_cursor: Option<HistoryCursor>,
+
+    validator: Option<Arc<dyn MessageValidator>>,
+    clock: HybridClock,
+    graylist: Graylist,
+    compression_peers: CompressionPeers,
+    validation: ValidationQueue,
+    metrics: Arc<PeerMetrics>,
+    faults: FaultLayer,
+    recorder: Option<MessageRecorder>,
+
     outside_tx: tokio::sync::mpsc::Sender<NodeMessage>,
+    network_tx: tokio::sync::broadcast::Sender<NetworkEvent>,
 
-    inside_rx: tokio::sync::mpsc::Receiver<Vec<u8>>,
-    inside_tx: tokio::sync::mpsc::Sender<Vec<u8>>,
+    inside_rx: tokio::sync::mpsc::Receiver<OutgoingMessage>,
+    inside_tx: tokio::sync::mpsc::Sender<OutgoingMessage>,
+    inside_control_rx: tokio::sync::mpsc::Receiver<OutgoingMessage>,
+    inside_control_tx: tokio::sync::mpsc::Sender<OutgoingMessage>,
 }
 
 impl MemberPeer {
     pub async fn create(
         config: MemberPeerConfig,
         key: identity::Keypair,
-    ) -> (Self, tokio::sync::mpsc::Receiver<NodeMessage>) {
-        let swarm = create_swarm::<ClientPeerBehaviour>(key).await;
+    ) -> Result<(Self, tokio::sync::mpsc::Receiver<NodeMessage>), String> {
+        if let Some(ttl) = config
+            .registration_ttl
+            .filter(|ttl| *ttl < MIN_REGISTRATION_TTL)
+        {
+            return Err(format!(
+                "Registration ttl of {}s is below the minimum of {}s",
+                ttl, MIN_REGISTRATION_TTL
+            ));
+        }
+        let mut swarm = create_swarm::<ClientPeerBehaviour>(key).await;
+        if let Some(peer_score) = &config.peer_score {
+            peer_score.apply(&mut swarm.behaviour_mut().gossip_sub, &config.workspace_id)?;
+        }
+
+        let (mut outside_tx, outside_rx) = tokio::sync::mpsc::channel::<NodeMessage>(100);
+        let recorder = open_recorder(config.recording.as_ref());
+        if let Some(recorder) = &recorder {
+            outside_tx = recorder.tap(config.workspace_id.clone(), outside_tx);
+        }
 
-        let (outside_tx, outside_rx) = tokio::sync::mpsc::channel::<NodeMessage>(100);
+        let (inside_tx, inside_rx) = tokio::sync::mpsc::channel::<OutgoingMessage>(100);
+        let (inside_control_tx, inside_control_rx) =
+            tokio::sync::mpsc::channel::<OutgoingMessage>(100);
 
-        let (inside_tx, inside_rx) = tokio::sync::mpsc::channel::<Vec<u8>>(100);
+        let admins = config
+            .admin
+            .iter()
+            .chain(config.standby_admins.iter())
+            .cloned()
+            .collect();
+        let history_cursor = config.history_replay;
 
-        (
+        Ok((
             Self {
                 config,
                 id: swarm.local_peer_id().to_string(),
                 swarm,
 
+                admins,
+                admin_index: 0,
+                registered_admin: None,
+                providers: HashSet::new(),
+                reconnect_at: None,
+                register_at: None,
+                history_cursor,
+
+                validator: None,
+                clock: HybridClock::new(),
+                graylist: Graylist::default(),
+                compression_peers: CompressionPeers::default(),
+                validation: ValidationQueue::new(),
+                metrics: Arc::new(PeerMetrics::default()),
+                faults: FaultLayer::new(),
+                recorder,
                 outside_tx,
+                network_tx: tokio::sync::broadcast::channel(NETWORK_EVENT_CAPACITY).0,
 
                 inside_tx,
                 inside_rx,
+                inside_control_tx,
+                inside_control_rx,
             },
             outside_rx,
-        )
+        ))
+    }
+
+    pub fn emitter(&self) -> Emitter {
+        Emitter::new(self.inside_tx.clone(), self.inside_control_tx.clone())
+    }
+
+    pub fn metrics(&self) -> Arc<PeerMetrics> {
+        self.metrics.clone()
+    }
+
+    /// Controls the faults injected into the gossipsub messages this peer receives, see
+    /// the `faults` module for what is left alone.
+    #[cfg(feature = "fault-injection")]
+    pub fn fault_injector(&self) -> FaultInjector {
+        self.faults.injector()
+    }
+
+    /// Network and lifecycle events, only those sent after subscribing are received.
+    pub fn network_events(&self) -> tokio::sync::broadcast::Receiver<NetworkEvent> {
+        self.network_tx.subscribe()
+    }
+
+    /// Messages received from now on are only delivered and forwarded if the validator accepts them.
+    pub fn set_validator(&mut self, validator: Arc<dyn MessageValidator>) {
+        self.validator = Some(validator);
+    }
+
+    async fn check_peer_scores(&mut self) {
+        let Some(peer_score) = &self.config.peer_score else {
+            return;
+        };
+        let graylisted = self
+            .graylist
+            .check(&self.swarm.behaviour().gossip_sub, peer_score);
+        for (peer_id, score) in graylisted {
+            let event = NodeMessage::new_event(
+                &mut self.clock,
+                self.id.clone(),
+                EventType::PeerGraylisted {
+                    peer_id: peer_id.to_string(),
+                    score,
+                },
+            );
+            if let Err(e) = self.outside_tx.send(event).await {
+                error!("Failed to send message to outside: {:?}", e);
+            }
+        }
     }
 
-    pub fn emitter(&self) -> tokio::sync::mpsc::Sender<Vec<u8>> {
-        self.inside_tx.clone()
+    async fn on_validated(&mut self, validated: Validated) {
+        validated.route.report(
+            &mut self.swarm.behaviour_mut().gossip_sub,
+            validated.acceptance,
+        );
+        let Some(msg) = validated.node_message else {
+            return;
+        };
+        self.clock.update(msg.hlc());
+        if self.removes_me(&msg) {
+            self.leave_workspace();
+        }
+        let span = msg.handling_span();
+        match self.outside_tx.send(msg).instrument(span).await {
+            Ok(_) => {}
+            Err(e) => {
+                error!("Failed to send message to outside: {:?}", e);
+            }
+        };
     }
-    pub async fn run(&mut self, cancellation_token: CancellationToken) {
+
+    pub async fn run(&mut self, cancellation_token: CancellationToken) -> ShutdownSummary {
         let name = self.config.name.clone();
         info!("Peer {:?}: {:?} Starting..", name.clone(), self.id.clone());
         let ext_address = Multiaddr::empty()
@@ -94,38 +307,107 @@ impl MemberPeer {
             .with(Protocol::QuicV1);
         self.swarm.add_external_address(ext_address.clone());
 
-        let admin_peer_id = self.config.admin_peer;
-        let rendezvous_point_address = self.config.rendezvous_point_address.clone();
+        if let Some(dht) = self.config.dht.clone() {
+            // Serve the DHT too, so later members can bootstrap from us.
+            if let Err(e) = self.swarm.listen_on(ext_address.clone()) {
+                error!("Failed to listen for DHT peers: {:?}", e);
+            }
+            let kad = &mut self.swarm.behaviour_mut().kad;
+            kad.set_mode(Some(kad::Mode::Server));
+            for node in dht.bootstrap {
+                kad.add_address(&node.peer_id, node.address);
+            }
+            if let Err(e) = kad.bootstrap() {
+                error!("Failed to bootstrap the DHT: {:?}", e);
+            }
+            kad.get_providers(workspace_key(&self.config.workspace_id));
+        }
 
-        let dial_opts = DialOpts::peer_id(admin_peer_id)
-            .addresses(vec![rendezvous_point_address])
-            .condition(PeerCondition::Always)
-            .build();
-        self.swarm.dial(dial_opts).unwrap();
+        if let Some(admin) = self.admins.first().cloned() {
+            let dial_opts = DialOpts::peer_id(admin.peer_id)
+                .addresses(vec![admin.address])
+                .condition(PeerCondition::Always)
+                .build();
+            self.swarm.dial(dial_opts).unwrap();
+        }
 
-        let name_copy = name.clone();
+        self.validation
+            .start(self.metrics.clone(), self.config.clock_skew);
+        let mut score_check = tokio::time::interval(SCORE_CHECK_INTERVAL);
         loop {
+            let reconnect_at = self.reconnect_at;
+            let register_at = self.register_at;
             select! {
                 _ = cancellation_token.cancelled() => {
                     break;
                 }
+                _ = score_check.tick() => {
+                    self.check_peer_scores().await;
+                }
+                validated = self.validation.next() => {
+                    self.on_validated(validated).await;
+                }
+                _ = tokio::time::sleep_until(reconnect_at.unwrap_or_else(Instant::now)), if reconnect_at.is_some() => {
+                    self.reconnect_at = None;
+                    self.dial_next_admin();
+                }
+                _ = tokio::time::sleep_until(register_at.unwrap_or_else(Instant::now)), if register_at.is_some() => {
+                    self.register_at = None;
+                    if let Some(admin) = self.admin_peer().filter(|a| self.swarm.is_connected(a)) {
+                        self.register(admin).await;
+                    }
+                }
+                fault = self.faults.next() => match fault {
+                    FaultEvent::Delayed(event) => self.process_event((*event).into()).await,
+                    FaultEvent::Partitioned(peers) => {
+                        for peer_id in peers {
+                            let _ = self.swarm.disconnect_peer_id(peer_id);
+                        }
+                    }
+                },
                 event = self.swarm.select_next_some() => {
+                    if let Some(network_event) = NetworkEvent::from_swarm(&event) {
+                        emit(&self.network_tx, network_event);
+                    }
                     match event {
-                       SwarmEvent::ConnectionEstablished { peer_id, .. } => {
-                            if peer_id ==  self.config.admin_peer {
-                                if let Err(error) = self.swarm.behaviour_mut().rendezvous.register(
-                                    rendezvous::Namespace::from_static("CEYLON-AI-PEER"),
-                                     self.config.admin_peer,
-                                    None,
-                                ) {
-                                    error!("Failed to register: {error}");
-                                }
+                        SwarmEvent::ConnectionEstablished { peer_id, .. } if self.faults.is_partitioned(&peer_id) => {
+                            warn!("Cutting connection to partitioned peer {}", peer_id);
+                            let _ = self.swarm.disconnect_peer_id(peer_id);
+                        }
+                       SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                            if self.admins.is_empty() && self.providers.contains(&peer_id) {
+                                info!("Found workspace admin {} in the DHT", peer_id);
+                                self.admins.push(AdminEndpoint::new(peer_id, endpoint.get_remote_address().clone()));
+                            }
+                            if Some(peer_id) == self.admin_peer() {
+                                self.register(peer_id).await;
                                 info!("Connection established with rendezvous point {}", peer_id);
                             }
                         }
-                        SwarmEvent::ConnectionClosed { peer_id, cause,.. } => {
-                            if peer_id == self.config.admin_peer {
+                        SwarmEvent::ConnectionClosed { peer_id, cause, num_established, .. } => {
+                            if num_established == 0 {
+                                self.graylist.forget(&peer_id);
+                                self.compression_peers.forget(&peer_id);
+                            }
+                            if Some(peer_id) == self.admin_peer() {
                                 error!("Lost connection to rendezvous point {:?}", cause);
+                                if num_established == 0 && (self.admins.len() > 1 || self.config.dht.is_some()) {
+                                    if self.config.history_replay.is_some() {
+                                        self.history_cursor = Some(HistoryCursor::Time(unix_millis()));
+                                    }
+                                    self.reconnect_at = Some(Instant::now() + ADMIN_RECONNECT_DELAY);
+                                }
+                            }
+                        }
+                        SwarmEvent::OutgoingConnectionError { peer_id: Some(peer_id), error, .. } => {
+                            if Some(peer_id) == self.admin_peer() && (self.admins.len() > 1 || self.config.dht.is_some()) {
+                                error!("Failed to reach admin {}: {:?}", peer_id, error);
+                                self.reconnect_at = Some(Instant::now() + ADMIN_RECONNECT_DELAY);
+                            }
+                        }
+                        SwarmEvent::Behaviour(ClientPeerEvent::GossipSub(event)) => {
+                            if let Some(event) = self.faults.inbound(event) {
+                                self.process_event(event.into()).await;
                             }
                         }
                         SwarmEvent::Behaviour(event) => {
@@ -137,28 +419,274 @@ impl MemberPeer {
                     }
                 }
 
+                message = self.inside_control_rx.recv() => {
+                    if let Some(message) = message {
+                        self.broadcast(message);
+                    }
+                }
                 message = self.inside_rx.recv() => {
                     if let Some(message) = message {
-                        let topic = gossipsub::IdentTopic::new(self.config.workspace_id.clone());
-
-                        let distributed_message = NodeMessage::Message {
-                            data: message,
-                            time: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs_f64() as u64,
-                            created_by: self.id.clone(),
-                        };
-                        match self.swarm.behaviour_mut().gossip_sub.publish(topic.clone(),distributed_message.to_bytes()){
-                            Ok(_) => {}
-                            Err(e) => {
-                                error!("Failed to broadcast message from {}: {:?} Topic {:?}",name_copy, e, topic.to_string());
-                            }
-                        }
+                        self.flush_control();
+                        self.broadcast(message);
                     }
                 }
             }
         }
+        self.shutdown().await
+    }
+
+    // Control messages queued meanwhile overtake the data message about to go out.
+    fn flush_control(&mut self) -> (usize, usize) {
+        let (mut flushed, mut dropped) = (0, 0);
+        while let Ok(message) = self.inside_control_rx.try_recv() {
+            if self.broadcast(message) {
+                flushed += 1;
+            } else {
+                dropped += 1;
+            }
+        }
+        (flushed, dropped)
+    }
+
+    fn broadcast(&mut self, message: OutgoingMessage) -> bool {
+        let topic = match message.priority {
+            Priority::Control => {
+                gossipsub::IdentTopic::new(control_topic(&self.config.workspace_id))
+            }
+            Priority::Data => gossipsub::IdentTopic::new(self.config.workspace_id.clone()),
+        };
+
+        let span = message.broadcast_span(&self.id, &topic.to_string());
+        span.in_scope(|| {
+            let distributed_message =
+                NodeMessage::new_message(&mut self.clock, self.id.clone(), message.data)
+                    .with_ttl(message.ttl.or(self.config.message_ttl));
+            match message.to {
+                Some(to) => self.send_direct(to, distributed_message),
+                None => self.publish(&topic, distributed_message),
+            }
+        })
+    }
+
+    // Sends a message to one peer through the admin, members are not connected to each other.
+    fn send_direct(&mut self, to: PeerId, message: NodeMessage) -> bool {
+        let Some(admin) = self.admin_peer() else {
+            error!("Direct: no admin to send to {:?} through", to);
+            return false;
+        };
+        let request = DirectRequest {
+            topic: self.config.workspace_id.clone(),
+            from: self.id.clone(),
+            to: to.to_string(),
+            data: message.to_bytes(),
+        };
+        self.swarm
+            .behaviour_mut()
+            .direct
+            .send_request(&admin, request);
+        if let Some(recorder) = &self.recorder {
+            recorder.record(Direction::Sent, &self.config.workspace_id, &message);
+        }
+        true
+    }
+
+    // Takes a message addressed to this member. Admins relay the messages of others, anyone
+    // else may only send its own.
+    fn on_direct(&mut self, peer: PeerId, request: DirectRequest) -> bool {
+        let relayed = self.is_admin(&peer);
+        let from = PeerId::from_str(&request.from)
+            .ok()
+            .filter(|from| relayed || *from == peer);
+        let Some(from) =
+            from.filter(|_| request.to == self.id && request.topic == self.config.workspace_id)
+        else {
+            warn!("Direct: refusing message from {:?}", peer);
+            return false;
+        };
+        let pending = PendingValidation {
+            route: Route::Direct,
+            message: gossipsub::Message {
+                source: Some(from),
+                data: request.data,
+                sequence_number: None,
+                topic: gossipsub::TopicHash::from_raw(request.topic),
+            },
+            validator: self.validator.clone(),
+        };
+        self.validation.submit(pending).is_ok()
+    }
+
+    fn publish(&mut self, topic: &gossipsub::IdentTopic, message: NodeMessage) -> bool {
+        let compression = self.compression_peers.config_for(
+            self.config.compression.as_ref(),
+            &self.swarm.behaviour().gossip_sub,
+            &topic.hash(),
+        );
+        match self
+            .swarm
+            .behaviour_mut()
+            .gossip_sub
+            .publish(topic.clone(), message.to_compressed_bytes(compression))
+        {
+            Ok(_) => {
+                if let Some(recorder) = &self.recorder {
+                    recorder.record(Direction::Sent, &topic.to_string(), &message);
+                }
+                true
+            }
+            Err(e) => {
+                error!(
+                    "Failed to broadcast message from {}: {:?} Topic {:?}",
+                    self.config.name,
+                    e,
+                    topic.to_string()
+                );
+                false
+            }
+        }
+    }
+
+    async fn shutdown(&mut self) -> ShutdownSummary {
+        info!("Member {} shutting down", self.config.name);
+        let deadline = Instant::now() + self.config.shutdown.drain_deadline;
+        let mut summary = ShutdownSummary::default();
+
+        (summary.flushed_messages, summary.dropped_messages) = self.flush_control();
+        while let Ok(message) = self.inside_rx.try_recv() {
+            if self.broadcast(message) {
+                summary.flushed_messages += 1;
+            } else {
+                summary.dropped_messages += 1;
+            }
+        }
+        let topic = gossipsub::IdentTopic::new(self.config.workspace_id.clone());
+        let left = NodeMessage::new_event(
+            &mut self.clock,
+            self.id.clone(),
+            EventType::AgentLeft {
+                peer_id: self.id.clone(),
+            },
+        );
+        self.publish(&topic, left);
+        for topic in [
+            topic,
+            gossipsub::IdentTopic::new(control_topic(&self.config.workspace_id)),
+        ] {
+            if let Err(e) = self.swarm.behaviour_mut().gossip_sub.unsubscribe(&topic) {
+                error!("Failed to unsubscribe from {}: {:?}", topic, e);
+            }
+        }
+        self.unregister();
+
+        close_connections(&mut self.swarm, deadline, &mut summary, &self.network_tx).await;
+        info!("Member {} stopped: {:?}", self.config.name, summary);
+        summary
+    }
+
+    // Whether an admin kicked or banned this member, anyone else may name it too.
+    fn removes_me(&self, msg: &NodeMessage) -> bool {
+        let NodeMessage::Event {
+            event: EventType::PeerKicked { peer_id } | EventType::PeerBanned { peer_id, .. },
+            created_by,
+            ..
+        } = msg
+        else {
+            return false;
+        };
+        *peer_id == self.id
+            && PeerId::from_str(created_by).is_ok_and(|author| self.is_admin(&author))
+    }
+
+    // Removed by the admin, so stop showing up in its registrations.
+    fn leave_workspace(&mut self) {
+        warn!("Member {} removed from the workspace", self.config.name);
+        self.unregister();
+    }
+
+    fn unregister(&mut self) {
+        self.register_at = None;
+        if let Some(admin) = self.registered_admin.take() {
+            self.swarm
+                .behaviour_mut()
+                .rendezvous
+                .unregister(rendezvous_namespace(&self.config.workspace_id), admin);
+        }
+    }
+
+    async fn register(&mut self, admin: PeerId) {
+        let namespace = rendezvous_namespace(&self.config.workspace_id);
+        if let Err(error) = self.swarm.behaviour_mut().rendezvous.register(
+            namespace.clone(),
+            admin,
+            self.config.registration_ttl,
+        ) {
+            self.on_registration_failed(namespace, error.to_string())
+                .await;
+        }
+    }
+
+    async fn on_registration_failed(&mut self, namespace: rendezvous::Namespace, error: String) {
+        error!("Failed to register in {}: {}", namespace, error);
+        self.register_at = Some(Instant::now() + REGISTRATION_RETRY_DELAY);
+        let event = NodeMessage::new_event(
+            &mut self.clock,
+            self.id.clone(),
+            EventType::RegistrationFailed {
+                namespace: namespace.to_string(),
+                error,
+            },
+        );
+        if let Err(e) = self.outside_tx.send(event).await {
+            error!("Failed to send message to outside: {:?}", e);
+        }
+    }
+
+    fn is_admin(&self, peer: &PeerId) -> bool {
+        self.admins.iter().any(|admin| admin.peer_id == *peer)
+    }
+
+    fn admin_peer(&self) -> Option<PeerId> {
+        self.admins.get(self.admin_index).map(|a| a.peer_id)
+    }
+
+    // Moves on to the next known admin, wrapping around to the primary.
+    // Discovered admins are forgotten instead, so the DHT is asked again.
+    fn dial_next_admin(&mut self) {
+        if self.config.dht.is_some() && self.config.admin.is_none() {
+            self.admins.clear();
+            self.admin_index = 0;
+        }
+        if self.admins.is_empty() {
+            self.providers.clear();
+            self.swarm
+                .behaviour_mut()
+                .kad
+                .get_providers(workspace_key(&self.config.workspace_id));
+            return;
+        }
+        self.admin_index = (self.admin_index + 1) % self.admins.len();
+        let admin = self.admins[self.admin_index].clone();
+        info!("Member {} trying admin {}", self.config.name, admin.peer_id);
+
+        let dial_opts = DialOpts::peer_id(admin.peer_id)
+            .addresses(vec![admin.address])
+            .condition(PeerCondition::Always)
+            .build();
+        if let Err(e) = self.swarm.dial(dial_opts) {
+            error!("Failed to dial admin {}: {:?}", admin.peer_id, e);
+            self.reconnect_at = Some(Instant::now() + ADMIN_RECONNECT_DELAY);
+        }
     }
 
     async fn process_event(&mut self, event: ClientPeerEvent) {
+        let network_event = match &event {
+            ClientPeerEvent::Rendezvous(event) => NetworkEvent::from_rendezvous_client(event),
+            ClientPeerEvent::GossipSub(event) => NetworkEvent::from_gossipsub(event),
+            _ => None,
+        };
+        if let Some(network_event) = network_event {
+            emit(&self.network_tx, network_event);
+        }
         let name_ = self.config.name.clone();
         match event {
             ClientPeerEvent::Rendezvous(event) => match event {
@@ -171,12 +699,63 @@ impl MemberPeer {
                             "Registered for namespace '{}' at rendezvous point {} for the next {} seconds",
                             namespace, rendezvous_node, ttl
                         );
-                    let topic = gossipsub::IdentTopic::new(self.config.workspace_id.clone());
-                    self.swarm
-                        .behaviour_mut()
-                        .gossip_sub
-                        .subscribe(&topic)
-                        .unwrap();
+                    for topic in [
+                        self.config.workspace_id.clone(),
+                        control_topic(&self.config.workspace_id),
+                    ] {
+                        self.swarm
+                            .behaviour_mut()
+                            .gossip_sub
+                            .subscribe(&gossipsub::IdentTopic::new(topic))
+                            .unwrap();
+                    }
+
+                    // Renew once three quarters of the ttl are gone.
+                    self.register_at = Some(Instant::now() + Duration::from_secs(ttl) * 3 / 4);
+
+                    let previous = self.registered_admin.replace(rendezvous_node);
+                    if previous != Some(rendezvous_node) {
+                        self.swarm.behaviour_mut().rendezvous.discover(
+                            Some(namespace),
+                            None,
+                            None,
+                            rendezvous_node,
+                        );
+                    }
+                    if previous.is_some_and(|p| p != rendezvous_node) {
+                        let event = NodeMessage::new_event(
+                            &mut self.clock,
+                            self.id.clone(),
+                            EventType::AdminChanged {
+                                peer_id: rendezvous_node.to_string(),
+                            },
+                        );
+                        if let Err(e) = self.outside_tx.send(event).await {
+                            error!("Failed to send message to outside: {:?}", e);
+                        }
+                    }
+                }
+                rendezvous::client::Event::RegisterFailed {
+                    namespace, error, ..
+                } => {
+                    self.on_registration_failed(namespace, format!("{:?}", error))
+                        .await;
+                }
+                rendezvous::client::Event::Discovered { registrations, .. } => {
+                    let peers = registrations
+                        .iter()
+                        .map(|r| r.record.peer_id())
+                        .filter(|p| p != self.swarm.local_peer_id())
+                        .map(|p| p.to_string())
+                        .collect();
+                    let event = NodeMessage::new_event(
+                        &mut self.clock,
+                        self.id.clone(),
+                        EventType::PeersDiscovered { peers },
+                    );
+                    if let Err(e) = self.outside_tx.send(event).await {
+                        error!("Failed to send message to outside: {:?}", e);
+                    }
                 }
                 _ => {
                     info!("Rendezvous: {:?}", event);
@@ -184,10 +763,37 @@ impl MemberPeer {
             },
 
             ClientPeerEvent::GossipSub(event) => match event {
+                // An admin hosting several workspaces announces all of their topics.
+                gossipsub::Event::Subscribed { topic, .. }
+                | gossipsub::Event::Unsubscribed { topic, .. }
+                    if topic.as_str() != self.config.workspace_id =>
+                {
+                    debug!("Ignoring subscription change of other topic {:?}", topic);
+                }
                 gossipsub::Event::Subscribed { peer_id, topic } => {
                     info!("Subscribed to topic: {:?} from peer: {:?}", topic, peer_id);
-                    if peer_id.to_string() == self.config.admin_peer.to_string() {
+                    let event = NodeMessage::new_event(
+                        &mut self.clock,
+                        peer_id.to_string(),
+                        EventType::Subscribe {
+                            topic: topic.to_string(),
+                            peer_id: peer_id.to_string(),
+                        },
+                    );
+                    if let Err(e) = self.outside_tx.send(event).await {
+                        error!("Failed to send message to outside: {:?}", e);
+                    }
+                    if Some(peer_id) == self.admin_peer() {
                         info!("Member {} Subscribe with Admin", name_.clone());
+                        if let Some(since) = self.history_cursor.take() {
+                            self.swarm.behaviour_mut().history.send_request(
+                                &peer_id,
+                                HistoryRequest {
+                                    topic: topic.to_string(),
+                                    since,
+                                },
+                            );
+                        }
                     }
                 }
 
@@ -196,19 +802,41 @@ impl MemberPeer {
                         "Unsubscribed from topic: {:?} from peer: {:?}",
                         topic, peer_id
                     );
-                    if peer_id.to_string() == self.config.admin_peer.to_string() {
+                    let event = NodeMessage::new_event(
+                        &mut self.clock,
+                        peer_id.to_string(),
+                        EventType::Unsubscribe {
+                            topic: topic.to_string(),
+                            peer_id: peer_id.to_string(),
+                        },
+                    );
+                    if let Err(e) = self.outside_tx.send(event).await {
+                        error!("Failed to send message to outside: {:?}", e);
+                    }
+                    if Some(peer_id) == self.admin_peer() {
                         info!("Member {} Unsubscribe with Admin", name_.clone());
                     }
                 }
 
-                gossipsub::Event::Message { message, .. } => {
-                    let msg = NodeMessage::from_bytes(message.data);
-                    match self.outside_tx.send(msg).await {
-                        Ok(_) => {}
-                        Err(e) => {
-                            error!("Failed to send message to outside: {:?}", e);
-                        }
+                gossipsub::Event::Message {
+                    propagation_source,
+                    message_id,
+                    message,
+                } => {
+                    let pending = PendingValidation {
+                        route: Route::Gossip {
+                            message_id,
+                            propagation_source,
+                        },
+                        message,
+                        validator: self.validator.clone(),
                     };
+                    if let Err(pending) = self.validation.submit(pending) {
+                        pending.route.report(
+                            &mut self.swarm.behaviour_mut().gossip_sub,
+                            MessageAcceptance::Ignore,
+                        );
+                    }
                 }
 
                 _ => {
@@ -216,9 +844,170 @@ impl MemberPeer {
                 }
             },
 
-            _ => {
-                // tracing::info!("Unhandled {:?}", other);
+            ClientPeerEvent::Direct(event) => match event {
+                request_response::Event::Message {
+                    peer,
+                    message:
+                        request_response::Message::Request {
+                            request, channel, ..
+                        },
+                } => {
+                    let accepted = self.on_direct(peer, request);
+                    if self
+                        .swarm
+                        .behaviour_mut()
+                        .direct
+                        .send_response(channel, DirectResponse { accepted })
+                        .is_err()
+                    {
+                        error!("Direct: failed to reply to {:?}", peer);
+                    }
+                }
+                request_response::Event::Message {
+                    peer,
+                    message: request_response::Message::Response { response, .. },
+                } if !response.accepted => {
+                    error!("Direct: {:?} did not take a message", peer);
+                }
+                request_response::Event::OutboundFailure { peer, error, .. } => {
+                    error!("Direct: failed to send to {:?}: {:?}", peer, error);
+                }
+                _ => {
+                    debug!("Direct: {:?}", event);
+                }
+            },
+
+            ClientPeerEvent::History(event) => match event {
+                // The history skips validation, only admins kept messages they validated.
+                request_response::Event::Message {
+                    peer,
+                    message: request_response::Message::Response { .. },
+                } if !self.is_admin(&peer) => {
+                    warn!("History: ignoring history from {:?}, not an admin", peer);
+                }
+                request_response::Event::Message {
+                    message: request_response::Message::Response { response, .. },
+                    ..
+                } => {
+                    info!(
+                        "Member {} received {} history messages",
+                        name_,
+                        response.entries.len()
+                    );
+                    for entry in response.entries {
+                        if entry
+                            .message
+                            .is_expired(unix_millis(), self.config.clock_skew)
+                        {
+                            self.metrics.count_expired_replayed(1);
+                            continue;
+                        }
+                        self.clock.update(entry.message.hlc());
+                        if let Err(e) = self.outside_tx.send(entry.message).await {
+                            error!("Failed to send message to outside: {:?}", e);
+                        }
+                    }
+                }
+                _ => {
+                    debug!("History: {:?}", event);
+                }
+            },
+
+            ClientPeerEvent::Mdns(event) => {
+                debug!("Mdns: {:?}", event);
+            }
+            ClientPeerEvent::Ping(event) => {
+                debug!("Ping: {:?}", event);
+            }
+            ClientPeerEvent::Identify(event) => {
+                if let identify::Event::Received { peer_id, info } = &event {
+                    self.compression_peers
+                        .identified(*peer_id, &info.agent_version);
+                    if self.config.dht.is_some() {
+                        for address in info.listen_addrs.iter() {
+                            self.swarm
+                                .behaviour_mut()
+                                .kad
+                                .add_address(peer_id, address.clone());
+                        }
+                    }
+                }
+                debug!("Identify: {:?}", event);
             }
+            ClientPeerEvent::Kad(event) => match event {
+                kad::Event::OutboundQueryProgressed {
+                    result:
+                        kad::QueryResult::GetProviders(Ok(kad::GetProvidersOk::FoundProviders {
+                            providers,
+                            ..
+                        })),
+                    ..
+                } => {
+                    let local_peer_id = *self.swarm.local_peer_id();
+                    for provider in providers {
+                        if provider == local_peer_id || !self.providers.insert(provider) {
+                            continue;
+                        }
+                        // Dial even when connected through the DHT already, the new
+                        // connection tells us which address reaches the admin.
+                        let dial_opts = DialOpts::peer_id(provider)
+                            .condition(PeerCondition::Always)
+                            .build();
+                        if let Err(e) = self.swarm.dial(dial_opts) {
+                            debug!("Failed to dial provider {}: {:?}", provider, e);
+                        }
+                    }
+                }
+                kad::Event::OutboundQueryProgressed {
+                    result: kad::QueryResult::GetProviders(result),
+                    step,
+                    ..
+                } if step.last => {
+                    debug!("Kad: provider lookup finished {:?}", result);
+                    if self.providers.is_empty() && self.reconnect_at.is_none() {
+                        self.reconnect_at = Some(Instant::now() + ADMIN_RECONNECT_DELAY);
+                    }
+                }
+                _ => {
+                    debug!("Kad: {:?}", event);
+                }
+            },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use libp2p::PeerId;
+
+    use crate::peer::message::clock::HybridClock;
+    use crate::peer::message::data::{EventType, NodeMessage};
+    use crate::peer::node::{create_key, MemberPeer, MemberPeerConfig};
+
+    #[tokio::test]
+    async fn only_admins_remove_a_member() {
+        let admin = PeerId::random();
+        let config = MemberPeerConfig::new(
+            "member".to_string(),
+            "kicks".to_string(),
+            admin.to_string(),
+            7000,
+        );
+        let (member, _member_rx) = MemberPeer::create(config, create_key()).await.unwrap();
+        let mut clock = HybridClock::new();
+        let kick = |clock: &mut HybridClock, created_by: &PeerId, peer_id: &str| {
+            NodeMessage::new_event(
+                clock,
+                created_by.to_string(),
+                EventType::PeerKicked {
+                    peer_id: peer_id.to_string(),
+                },
+            )
+        };
+
+        assert!(member.removes_me(&kick(&mut clock, &admin, &member.id)));
+        assert!(!member.removes_me(&kick(&mut clock, &admin, &PeerId::random().to_string())));
+        // Another member of the workspace forging the kick.
+        assert!(!member.removes_me(&kick(&mut clock, &PeerId::random(), &member.id)));
+    }
+}
@@ -0,0 +1,29 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters of a running peer, shared with the application.
+#[derive(Debug, Default)]
+pub struct PeerMetrics {
+    expired_received: AtomicU64,
+    expired_replayed: AtomicU64,
+}
+
+impl PeerMetrics {
+    /// Expired messages dropped on receipt, they were neither delivered nor forwarded.
+    pub fn expired_received(&self) -> u64 {
+        self.expired_received.load(Ordering::Relaxed)
+    }
+
+    /// Expired messages left out of history replays.
+    pub fn expired_replayed(&self) -> u64 {
+        self.expired_replayed.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn count_expired_received(&self) {
+        self.expired_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn count_expired_replayed(&self, count: usize) {
+        self.expired_replayed
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+}
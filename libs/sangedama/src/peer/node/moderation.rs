@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use libp2p::PeerId;
+use tokio::sync::{mpsc, oneshot};
+use tracing::error;
+
+/// How long a removed peer is kept connected, so it gets the event telling it so.
+pub(crate) const KICK_GRACE: Duration = Duration::from_millis(500);
+
+/// Whether the admin carried out a command, with the reason when it did not.
+pub type CommandReply = oneshot::Sender<Result<(), String>>;
+
+#[derive(Debug)]
+pub enum AdminCommand {
+    Kick {
+        peer_id: PeerId,
+        reply: CommandReply,
+    },
+    Ban {
+        peer_id: PeerId,
+        /// `None` bans until unbanned.
+        duration: Option<Duration>,
+        reply: CommandReply,
+    },
+    Unban {
+        peer_id: PeerId,
+        reply: CommandReply,
+    },
+}
+
+/// Lets the owner of a running admin remove members from its workspace.
+#[derive(Debug, Clone)]
+pub struct AdminControl {
+    tx: mpsc::Sender<AdminCommand>,
+}
+
+impl AdminControl {
+    pub(crate) fn new(tx: mpsc::Sender<AdminCommand>) -> Self {
+        Self { tx }
+    }
+
+    /// Disconnects the peer, it may join again. Fails when it is not a member.
+    pub async fn kick(&self, peer_id: PeerId) -> Result<(), String> {
+        self.send(|reply| AdminCommand::Kick { peer_id, reply })
+            .await
+    }
+
+    /// Disconnects the peer and refuses its connections until the ban expires.
+    pub async fn ban(&self, peer_id: PeerId, duration: Option<Duration>) -> Result<(), String> {
+        self.send(|reply| AdminCommand::Ban {
+            peer_id,
+            duration,
+            reply,
+        })
+        .await
+    }
+
+    /// Fails when the peer is not banned.
+    pub async fn unban(&self, peer_id: PeerId) -> Result<(), String> {
+        self.send(|reply| AdminCommand::Unban { peer_id, reply })
+            .await
+    }
+
+    async fn send(&self, command: impl FnOnce(CommandReply) -> AdminCommand) -> Result<(), String> {
+        let (reply, result) = oneshot::channel();
+        self.tx
+            .send(command(reply))
+            .await
+            .map_err(|_| "Admin is not running".to_string())?;
+        result
+            .await
+            .map_err(|_| "Admin stopped before answering".to_string())?
+    }
+}
+
+/// Banned peers with the unix time in milliseconds their ban ends, `None` for good.
+/// Kept in a JSON file when a path is given, so bans survive restarts.
+pub(crate) struct BanList {
+    path: Option<PathBuf>,
+    bans: HashMap<PeerId, Option<u64>>,
+}
+
+impl BanList {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut bans = HashMap::new();
+        if let Some(path) = &path {
+            if let Ok(content) = fs::read_to_string(path) {
+                match serde_json::from_str::<HashMap<String, Option<u64>>>(&content) {
+                    Ok(stored) => {
+                        bans = stored
+                            .into_iter()
+                            .filter_map(|(p, until)| PeerId::from_str(&p).ok().map(|p| (p, until)))
+                            .collect();
+                    }
+                    Err(e) => error!("Failed to read ban list {:?}: {:?}", path, e),
+                }
+            }
+        }
+        Self { path, bans }
+    }
+
+    pub fn contains(&self, peer_id: &PeerId) -> bool {
+        self.bans.contains_key(peer_id)
+    }
+
+    pub fn peers(&self) -> Vec<PeerId> {
+        self.bans.keys().cloned().collect()
+    }
+
+    /// The ban applies either way, the error tells it could not be persisted.
+    pub fn insert(&mut self, peer_id: PeerId, until: Option<u64>) -> Result<(), String> {
+        self.bans.insert(peer_id, until);
+        self.save()
+    }
+
+    pub fn remove(&mut self, peer_id: &PeerId) -> bool {
+        let removed = self.bans.remove(peer_id).is_some();
+        if removed {
+            if let Err(e) = self.save() {
+                error!("{}", e);
+            }
+        }
+        removed
+    }
+
+    /// Drops and returns the bans ended by `now`.
+    pub fn expire(&mut self, now: u64) -> Vec<PeerId> {
+        let expired: Vec<PeerId> = self
+            .bans
+            .iter()
+            .filter(|(_, until)| until.is_some_and(|until| until <= now))
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        if !expired.is_empty() {
+            for peer_id in &expired {
+                self.bans.remove(peer_id);
+            }
+            if let Err(e) = self.save() {
+                error!("{}", e);
+            }
+        }
+        expired
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let stored: HashMap<String, Option<u64>> = self
+            .bans
+            .iter()
+            .map(|(peer_id, until)| (peer_id.to_string(), *until))
+            .collect();
+        fs::write(path, serde_json::to_vec(&stored).unwrap())
+            .map_err(|e| format!("Failed to write ban list {:?}: {}", path, e))
+    }
+}
@@ -1,3 +1,7 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
 use libp2p::{identity, PeerId};
 
 pub fn create_key() -> identity::Keypair {
@@ -11,3 +15,72 @@ pub fn create_key_from_bytes(bytes: Vec<u8>) -> identity::Keypair {
 pub fn get_peer_id(key: &identity::Keypair) -> PeerId {
     key.public().to_peer_id()
 }
+
+/// Reads the key stored at `path`, or generates one and stores it there,
+/// so a peer keeps its id across restarts. On unix the file is created readable
+/// by its owner only, and keys readable by group or others are refused.
+pub fn load_or_create_key(path: &Path) -> io::Result<identity::Keypair> {
+    if path.exists() {
+        check_key_permissions(path)?;
+        let bytes = fs::read(path)?;
+        return identity::Keypair::from_protobuf_encoding(&bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+    }
+    let key = create_key();
+    let bytes = key.to_protobuf_encoding().map_err(io::Error::other)?;
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(&bytes)?;
+    Ok(key)
+}
+
+#[cfg(unix)]
+fn check_key_permissions(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)?.permissions().mode();
+    if mode & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "key file {:?} is accessible by group or others (mode {:o}), restrict it to 0600",
+                path,
+                mode & 0o777
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_key_permissions(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[test]
+    fn key_file_is_private() {
+        let path = std::env::temp_dir().join(format!("peer-key-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let key = load_or_create_key(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(
+            get_peer_id(&load_or_create_key(&path).unwrap()),
+            get_peer_id(&key)
+        );
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        let err = load_or_create_key(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use libp2p::gossipsub::{self, PeerScoreParams, PeerScoreThresholds, TopicScoreParams};
+use libp2p::PeerId;
+use tracing::warn;
+
+use crate::peer::node::lanes::control_topic;
+
+pub(crate) const SCORE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct PeerScoreConfig {
+    pub params: PeerScoreParams,
+    pub thresholds: PeerScoreThresholds,
+    /// Applied to the workspace topics, this is where rejected messages are penalized.
+    pub topic: TopicScoreParams,
+}
+
+impl Default for PeerScoreConfig {
+    fn default() -> Self {
+        Self {
+            params: PeerScoreParams {
+                // Agents of a workspace often share a host, that is no reason for suspicion.
+                ip_colocation_factor_weight: 0.0,
+                ..PeerScoreParams::default()
+            },
+            thresholds: PeerScoreThresholds::default(),
+            topic: TopicScoreParams {
+                topic_weight: 1.0,
+                // Keep the rewards for good behaviour small, so they cannot outweigh
+                // a handful of rejected messages.
+                time_in_mesh_weight: 0.01,
+                time_in_mesh_quantum: Duration::from_secs(1),
+                first_message_deliveries_weight: 0.5,
+                first_message_deliveries_cap: 40.0,
+                // Workspaces can be quiet for long, do not punish peers for it.
+                mesh_message_deliveries_weight: 0.0,
+                mesh_failure_penalty_weight: 0.0,
+                invalid_message_deliveries_weight: -10.0,
+                // Rejected messages are held against a peer for about ten minutes.
+                invalid_message_deliveries_decay: gossipsub::score_parameter_decay(
+                    Duration::from_secs(600),
+                ),
+                ..TopicScoreParams::default()
+            },
+        }
+    }
+}
+
+impl PeerScoreConfig {
+    pub(crate) fn apply(
+        &self,
+        gossip_sub: &mut gossipsub::Behaviour,
+        workspace_id: &str,
+    ) -> Result<(), String> {
+        let mut params = self.params.clone();
+        for topic in [workspace_id.to_string(), control_topic(workspace_id)] {
+            params
+                .topics
+                .insert(gossipsub::IdentTopic::new(topic).hash(), self.topic.clone());
+        }
+        gossip_sub.with_peer_score(params, self.thresholds.clone())
+    }
+
+    /// Scores one more workspace hosted by an admin that already applied the config.
+    pub(crate) fn apply_to_workspace(
+        &self,
+        gossip_sub: &mut gossipsub::Behaviour,
+        workspace_id: &str,
+    ) -> Result<(), String> {
+        for topic in [workspace_id.to_string(), control_topic(workspace_id)] {
+            gossip_sub
+                .set_topic_params(gossipsub::IdentTopic::new(topic), self.topic.clone())
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+/// Peers currently scored below the graylist threshold, so each is reported once.
+#[derive(Debug, Default)]
+pub(crate) struct Graylist {
+    peers: HashSet<PeerId>,
+}
+
+impl Graylist {
+    /// Peers whose score dropped below the graylist threshold since the last check.
+    /// Peers that recovered are forgotten, so they are reported again if they relapse.
+    pub fn check(
+        &mut self,
+        gossip_sub: &gossipsub::Behaviour,
+        config: &PeerScoreConfig,
+    ) -> Vec<(PeerId, f64)> {
+        let threshold = config.thresholds.graylist_threshold;
+        let mut newly = vec![];
+        let peers: Vec<PeerId> = gossip_sub.all_peers().map(|(peer, _)| *peer).collect();
+        for peer in peers {
+            let Some(score) = gossip_sub.peer_score(&peer) else {
+                continue;
+            };
+            if score < threshold {
+                if self.peers.insert(peer) {
+                    warn!("Peer {} graylisted with score {}", peer, score);
+                    newly.push((peer, score));
+                }
+            } else {
+                self.peers.remove(&peer);
+            }
+        }
+        newly
+    }
+
+    /// Drops a peer that disconnected, gossipsub forgets its score too.
+    pub fn forget(&mut self, peer_id: &PeerId) {
+        self.peers.remove(peer_id);
+    }
+}
@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use futures::StreamExt;
+use libp2p::swarm::{NetworkBehaviour, SwarmEvent};
+use libp2p::Swarm;
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+use tracing::debug;
+
+use crate::peer::node::events::{emit, NetworkEvent};
+
+/// Time given to published messages to leave before connections are closed.
+const FLUSH_PERIOD: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone)]
+pub struct ShutdownConfig {
+    /// Upper bound for flushing queued messages and closing connections when the peer stops.
+    pub drain_deadline: Duration,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            drain_deadline: Duration::from_secs(2),
+        }
+    }
+}
+
+/// What a peer managed to do while shutting down.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownSummary {
+    /// Queued outgoing messages published before leaving.
+    pub flushed_messages: usize,
+    /// Queued outgoing messages that could not be published.
+    pub dropped_messages: usize,
+    pub closed_connections: usize,
+    /// The drain deadline passed before every connection was closed.
+    pub timed_out: bool,
+}
+
+/// Keeps the swarm going for a moment so pending messages go out, then closes every connection.
+pub(crate) async fn close_connections<B: NetworkBehaviour>(
+    swarm: &mut Swarm<B>,
+    deadline: Instant,
+    summary: &mut ShutdownSummary,
+    network_tx: &broadcast::Sender<NetworkEvent>,
+) {
+    let flushed_at = deadline.min(Instant::now() + FLUSH_PERIOD);
+    while let Ok(event) = tokio::time::timeout_at(flushed_at, swarm.select_next_some()).await {
+        if let Some(network_event) = NetworkEvent::from_swarm(&event) {
+            emit(network_tx, network_event);
+        }
+    }
+
+    let peers: Vec<_> = swarm.connected_peers().cloned().collect();
+    for peer_id in peers {
+        let _ = swarm.disconnect_peer_id(peer_id);
+    }
+    while swarm.network_info().num_peers() > 0 {
+        match tokio::time::timeout_at(deadline, swarm.select_next_some()).await {
+            Ok(event) => {
+                if let Some(network_event) = NetworkEvent::from_swarm(&event) {
+                    emit(network_tx, network_event);
+                }
+                if let SwarmEvent::ConnectionClosed { peer_id, .. } = event {
+                    debug!("Closed connection to {}", peer_id);
+                    summary.closed_connections += 1;
+                }
+            }
+            Err(_) => {
+                summary.timed_out = true;
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Helpers for the tests that run an admin and its members on localhost.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+use tokio::sync::mpsc;
+use tokio::time::{timeout_at, Instant};
+use tokio_util::sync::CancellationToken;
+
+use crate::peer::message::data::NodeMessage;
+use crate::peer::node::{create_key, AdminPeer, Emitter, MemberPeer, MemberPeerConfig};
+
+/// Payloads of the messages received until `within` has passed.
+pub(crate) async fn received(
+    rx: &mut mpsc::Receiver<NodeMessage>,
+    within: Duration,
+) -> Vec<Vec<u8>> {
+    let deadline = Instant::now() + within;
+    let mut payloads = vec![];
+    while let Ok(Some(message)) = timeout_at(deadline, rx.recv()).await {
+        if let NodeMessage::Message { data, .. } = message {
+            payloads.push(data);
+        }
+    }
+    payloads
+}
+
+/// Runs the admin on a free localhost port until cancelled, returning the port.
+pub(crate) async fn run_admin(mut admin: AdminPeer, cancel: &CancellationToken) -> u16 {
+    let mut addresses = admin.listen_addresses();
+    let address = Multiaddr::from_str("/ip4/127.0.0.1/udp/0/quic-v1").unwrap();
+    let token = cancel.clone();
+    tokio::spawn(async move { admin.run(Some(address), token).await });
+    let port = addresses
+        .wait_for(|addresses| !addresses.is_empty())
+        .await
+        .unwrap()
+        .iter()
+        .flat_map(|address| address.iter())
+        .find_map(|protocol| match protocol {
+            Protocol::Udp(port) => Some(port),
+            _ => None,
+        })
+        .unwrap();
+    port
+}
+
+/// A member of the workspace running until cancelled, with its id, emitter and messages.
+pub(crate) async fn run_member(
+    name: &str,
+    workspace: &str,
+    admin_id: &str,
+    port: u16,
+    cancel: &CancellationToken,
+) -> (String, Emitter, mpsc::Receiver<NodeMessage>) {
+    let config = MemberPeerConfig::new(
+        name.to_string(),
+        workspace.to_string(),
+        admin_id.to_string(),
+        port,
+    );
+    let (mut member, member_rx) = MemberPeer::create(config, create_key()).await.unwrap();
+    let (id, emitter) = (member.id.clone(), member.emitter());
+    let token = cancel.clone();
+    tokio::spawn(async move { member.run(token).await });
+    (id, emitter, member_rx)
+}
+
+/// Sends until the admin hears from the member, publishing fails until it is subscribed.
+/// The messages that were still on their way are drained.
+pub(crate) async fn join(emitter: &Emitter, admin_rx: &mut mpsc::Receiver<NodeMessage>) {
+    for _ in 0..40 {
+        emitter.send(b"hello".to_vec()).await.unwrap();
+        if !received(admin_rx, Duration::from_millis(250))
+            .await
+            .is_empty()
+        {
+            received(admin_rx, Duration::from_millis(500)).await;
+            return;
+        }
+    }
+    panic!("member never reached the admin");
+}
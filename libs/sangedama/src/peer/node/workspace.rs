@@ -0,0 +1,111 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use libp2p::{gossipsub, rendezvous, PeerId};
+use tokio::sync::mpsc;
+
+use crate::peer::message::data::{NodeMessage, OutgoingMessage};
+use crate::peer::message::history::{HistoryConfig, MessageHistory};
+use crate::peer::message::validation::MessageValidator;
+use crate::peer::node::discovery::rendezvous_namespace;
+use crate::peer::node::lanes::{control_topic, Emitter};
+
+/// Who may take part in a workspace.
+#[derive(Debug, Clone, Default)]
+pub enum AccessPolicy {
+    #[default]
+    Open,
+    /// Only these peers may take part.
+    AllowList(HashSet<PeerId>),
+    /// Everyone but these peers may take part.
+    DenyList(HashSet<PeerId>),
+}
+
+impl AccessPolicy {
+    pub fn allows(&self, peer_id: &PeerId) -> bool {
+        match self {
+            AccessPolicy::Open => true,
+            AccessPolicy::AllowList(peers) => peers.contains(peer_id),
+            AccessPolicy::DenyList(peers) => !peers.contains(peer_id),
+        }
+    }
+}
+
+/// A workspace hosted by an admin next to the one it was created for.
+#[derive(Debug, Clone, Default)]
+pub struct WorkspaceConfig {
+    pub workspace_id: String,
+    pub history: HistoryConfig,
+    pub access: AccessPolicy,
+    /// TTL of the messages broadcast without one of their own.
+    pub message_ttl: Option<Duration>,
+}
+
+impl WorkspaceConfig {
+    pub fn new(workspace_id: String) -> Self {
+        Self {
+            workspace_id,
+            history: HistoryConfig::default(),
+            access: AccessPolicy::default(),
+            message_ttl: None,
+        }
+    }
+}
+
+/// State an admin keeps apart for every workspace it hosts.
+pub(crate) struct HostedWorkspace {
+    pub workspace_id: String,
+    pub topic: gossipsub::IdentTopic,
+    pub control_topic: gossipsub::IdentTopic,
+    pub namespace: rendezvous::Namespace,
+    pub access: AccessPolicy,
+    pub message_ttl: Option<Duration>,
+    pub history: MessageHistory,
+    pub validator: Option<Arc<dyn MessageValidator>>,
+    pub outside_tx: mpsc::Sender<NodeMessage>,
+    pub inside_tx: mpsc::Sender<OutgoingMessage>,
+    pub inside_control_tx: mpsc::Sender<OutgoingMessage>,
+}
+
+impl HostedWorkspace {
+    pub fn new(
+        config: WorkspaceConfig,
+    ) -> (
+        Self,
+        mpsc::Receiver<NodeMessage>,
+        mpsc::Receiver<OutgoingMessage>,
+        mpsc::Receiver<OutgoingMessage>,
+    ) {
+        let (outside_tx, outside_rx) = mpsc::channel::<NodeMessage>(100);
+        let (inside_tx, inside_rx) = mpsc::channel::<OutgoingMessage>(100);
+        let (inside_control_tx, inside_control_rx) = mpsc::channel::<OutgoingMessage>(100);
+        (
+            Self {
+                topic: gossipsub::IdentTopic::new(config.workspace_id.clone()),
+                control_topic: gossipsub::IdentTopic::new(control_topic(&config.workspace_id)),
+                namespace: rendezvous_namespace(&config.workspace_id),
+                workspace_id: config.workspace_id,
+                access: config.access,
+                message_ttl: config.message_ttl,
+                history: MessageHistory::new(config.history),
+                validator: None,
+                outside_tx,
+                inside_tx,
+                inside_control_tx,
+            },
+            outside_rx,
+            inside_rx,
+            inside_control_rx,
+        )
+    }
+
+    pub fn emitter(&self) -> Emitter {
+        Emitter::new(self.inside_tx.clone(), self.inside_control_tx.clone())
+    }
+
+    /// Whether messages on the topic belong to this workspace, on either lane.
+    pub fn carries(&self, topic: &gossipsub::TopicHash) -> bool {
+        *topic == self.topic.hash() || *topic == self.control_topic.hash()
+    }
+}
@@ -0,0 +1,72 @@
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use tracing::error;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Debug, Clone)]
+pub struct TracingConfig {
+    pub service_name: String,
+    /// Filter used when `RUST_LOG` is not set.
+    pub level: String,
+    /// OTLP/HTTP traces endpoint, e.g. `http://localhost:4318/v1/traces`.
+    /// Without it spans are still given trace ids and propagated, just not exported.
+    pub otlp_endpoint: Option<String>,
+}
+
+impl TracingConfig {
+    pub fn new(service_name: String) -> Self {
+        Self {
+            service_name,
+            level: "info".to_string(),
+            otlp_endpoint: None,
+        }
+    }
+}
+
+/// Flushes pending spans to the exporter when dropped.
+pub struct TracingGuard {
+    provider: TracerProvider,
+}
+
+impl Drop for TracingGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.provider.shutdown() {
+            error!("Failed to shut down tracer provider: {:?}", e);
+        }
+    }
+}
+
+/// Installs the global subscriber, logging to stderr and recording spans with OpenTelemetry
+/// so message trace contexts can be propagated. Must be called inside a tokio runtime.
+pub fn init_tracing(config: TracingConfig) -> Result<TracingGuard, String> {
+    let mut builder = TracerProvider::builder().with_resource(Resource::new(vec![KeyValue::new(
+        "service.name",
+        config.service_name.clone(),
+    )]));
+    if let Some(endpoint) = &config.otlp_endpoint {
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(endpoint)
+            .build()
+            .map_err(|e| format!("Failed to create OTLP exporter: {:?}", e))?;
+        builder = builder.with_batch_exporter(exporter, runtime::Tokio);
+    }
+    let provider = builder.build();
+    let tracer = provider.tracer("sangedama");
+
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.level));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .try_init()
+        .map_err(|e| format!("Failed to install tracing subscriber: {:?}", e))?;
+
+    Ok(TracingGuard { provider })
+}